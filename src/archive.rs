@@ -0,0 +1,252 @@
+// Respacks come either as a zip or as a plain folder (handy for packs that are still being made)
+// Both get read through PackArchive so the loader doesn't need to care which it is
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::ffi::OsStr;
+
+use zip::read::ZipArchive;
+use zip::write::{FileOptions, ZipWriter};
+use zip::CompressionMethod;
+
+use Result;
+
+pub enum PackArchive {
+	Zip(ZipArchive<File>),
+	Dir(Vec<PathBuf>),
+}
+
+// A single file in the pack
+pub struct PackFile<'a> {
+	pub path: PathBuf,
+	pub size: u64,
+	// Size on disk, which is what the loading progress is counted in
+	pub stored_size: u64,
+	pub reader: Box<dyn Read + 'a>,
+}
+
+impl PackArchive {
+	pub fn open<T: AsRef<Path>>(path: T) -> Result<Self> {
+		let path = path.as_ref();
+		if path.is_dir() {
+			let mut files = Vec::new();
+			list_files(path, &mut files)?;
+			files.sort();
+
+			Ok(PackArchive::Dir(files))
+		} else {
+			Ok(PackArchive::Zip(ZipArchive::new(File::open(path)?)?))
+		}
+	}
+
+	// Total size of all the files as stored on disk
+	pub fn total_size(&mut self) -> Result<u64> {
+		match *self {
+			PackArchive::Zip(ref mut archive) => {
+				let mut total = 0;
+				for i in 0..archive.len() {
+					total += archive.by_index(i)?.compressed_size();
+				}
+				Ok(total)
+			}
+			PackArchive::Dir(ref files) => {
+				let mut total = 0;
+				for file in files.iter() {
+					total += fs::metadata(file)?.len();
+				}
+				Ok(total)
+			}
+		}
+	}
+
+	pub fn num_files(&self) -> usize {
+		match *self {
+			PackArchive::Zip(ref archive) => archive.len(),
+			PackArchive::Dir(ref files) => files.len(),
+		}
+	}
+
+	pub fn by_index<'a>(&'a mut self, index: usize) -> Result<PackFile<'a>> {
+		match *self {
+			PackArchive::Zip(ref mut archive) => {
+				let file = archive.by_index(index)?;
+				Ok(PackFile {
+					path: file.name().into(),
+					size: file.size(),
+					stored_size: file.compressed_size(),
+					reader: Box::new(file),
+				})
+			}
+			PackArchive::Dir(ref files) => {
+				let path = files.get(index).ok_or("File index out of range")?;
+				let file = File::open(path)?;
+				let size = file.metadata()?.len();
+				Ok(PackFile {
+					path: path.clone(),
+					size,
+					stored_size: size,
+					reader: Box::new(file),
+				})
+			}
+		}
+	}
+}
+
+// Where a pack gets written, a zip if the path ends in .zip and a folder otherwise
+pub enum PackWriter {
+	Zip(ZipWriter<File>),
+	Dir(PathBuf),
+}
+
+impl PackWriter {
+	pub fn create<T: AsRef<Path>>(path: T) -> Result<Self> {
+		let path = path.as_ref();
+		if path.extension().and_then(OsStr::to_str) == Some("zip") {
+			Ok(PackWriter::Zip(ZipWriter::new(File::create(path)?)))
+		} else {
+			fs::create_dir_all(path)?;
+			Ok(PackWriter::Dir(path.to_owned()))
+		}
+	}
+
+	// Name is from the top of the pack, with / between folders
+	pub fn add_file(&mut self, name: &str, data: &[u8]) -> Result<()> {
+		match *self {
+			PackWriter::Zip(ref mut writer) => {
+				// Audio and images are already compressed
+				let method = if name.ends_with(".xml") {
+					CompressionMethod::Deflated
+				} else {
+					CompressionMethod::Stored
+				};
+				writer.start_file(name, FileOptions::default().compression_method(method))?;
+				writer.write_all(data)?;
+			}
+			PackWriter::Dir(ref dir) => {
+				let path = dir.join(name);
+				if let Some(parent) = path.parent() {
+					fs::create_dir_all(parent)?;
+				}
+				fs::write(path, data)?;
+			}
+		}
+		Ok(())
+	}
+
+	pub fn finish(self) -> Result<()> {
+		if let PackWriter::Zip(mut writer) = self {
+			writer.finish()?;
+		}
+		Ok(())
+	}
+}
+
+// Swaps out one file in a pack, going by the path it was read from
+// Zips get copied with the new file in its place, then moved over the old one
+pub fn replace_file<T: AsRef<Path>>(pack: T, file: &Path, data: &[u8]) -> Result<()> {
+	let pack = pack.as_ref();
+	if pack.is_dir() {
+		fs::write(file, data)?;
+		return Ok(());
+	}
+
+	let mut archive = ZipArchive::new(File::open(pack)?)?;
+	// Not a .zip, so it doesn't get picked up as a pack halfway through
+	let temp_path = pack.with_extension("zip.tmp");
+	let mut writer = ZipWriter::new(File::create(&temp_path)?);
+	for i in 0..archive.len() {
+		let mut entry = archive.by_index(i)?;
+		let name = entry.name().to_owned();
+		let method = match entry.compression() {
+			CompressionMethod::Stored => CompressionMethod::Stored,
+			_ => CompressionMethod::Deflated,
+		};
+		let options = FileOptions::default().compression_method(method);
+		if name.ends_with('/') {
+			writer.add_directory(name, options)?;
+			continue;
+		}
+
+		writer.start_file(name.clone(), options)?;
+		if Path::new(&name) == file {
+			writer.write_all(data)?;
+		} else {
+			io::copy(&mut entry, &mut writer)?;
+		}
+	}
+	writer.finish()?;
+	drop(archive);
+
+	fs::rename(&temp_path, pack)?;
+	Ok(())
+}
+
+// Everything under a folder, leaving out hidden files and folders like .git
+fn list_files(dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let path = entry.path();
+		if is_hidden(&path) {
+			continue;
+		}
+		// Doesn't follow links to folders, in case they loop back round
+		if entry.file_type()?.is_dir() {
+			list_files(&path, files)?;
+		} else if path.is_file() {
+			files.push(path);
+		}
+	}
+	Ok(())
+}
+
+fn is_hidden(path: &Path) -> bool {
+	path.file_name()
+		.and_then(OsStr::to_str)
+		.is_some_and(|name| name.starts_with('.'))
+}
+
+// Everything in a folder that looks like a pack, in name order
+// Nothing if the folder isn't there
+pub fn list_respacks<T: AsRef<Path>>(dir: T) -> Vec<PathBuf> {
+	let mut packs: Vec<PathBuf> = match fs::read_dir(dir) {
+		Ok(entries) => entries
+			.filter_map(::std::result::Result::ok)
+			.map(|entry| entry.path())
+			.filter(|path| !is_hidden(path) && is_respack(path))
+			.collect(),
+		Err(_) => Vec::new(),
+	};
+	packs.sort();
+	packs
+}
+
+// Default name of the pack, if info.xml doesn't give one
+pub fn pack_name<T: AsRef<Path>>(path: T) -> String {
+	let path = path.as_ref();
+	let name = if path.is_dir() {
+		path.file_name()
+	} else {
+		path.file_stem()
+	};
+	name.and_then(OsStr::to_str).unwrap_or("???").to_owned()
+}
+
+// Whether something in the respacks folder looks like it could be a pack
+pub fn is_respack<T: AsRef<Path>>(path: T) -> bool {
+	let path = path.as_ref();
+	path.is_dir() || path.extension().and_then(OsStr::to_str) == Some("zip")
+}
+
+// Packs in the config are given by name - prefer a folder if there is one
+pub fn find_respack<T: AsRef<Path>, S: AsRef<str>>(dir: T, name: S) -> PathBuf {
+	let dir = dir.as_ref();
+	let name = name.as_ref();
+
+	let folder = dir.join(name);
+	if folder.is_dir() {
+		folder
+	} else {
+		dir.join(format!("{}.zip", name))
+	}
+}
@@ -0,0 +1,525 @@
+// Picks a decoder for the audio in a respack
+// Everything ends up as the same AudioData once it's decoded
+
+use std::io::Cursor;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use rodio::{Decoder, Source};
+
+use mp3::{self, Mp3Decoder};
+use clock;
+use duration_to_secs;
+use AudioData;
+use Result;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AudioFormat {
+	Mp3,
+	Vorbis,
+	Wav,
+	Flac,
+}
+
+impl AudioFormat {
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		match extension.to_lowercase().as_ref() {
+			"mp3" => Some(AudioFormat::Mp3),
+			"ogg" | "oga" => Some(AudioFormat::Vorbis),
+			"wav" => Some(AudioFormat::Wav),
+			"flac" => Some(AudioFormat::Flac),
+			_ => None,
+		}
+	}
+
+	// What it gets saved as
+	pub fn extension(&self) -> &'static str {
+		match *self {
+			AudioFormat::Mp3 => "mp3",
+			AudioFormat::Vorbis => "ogg",
+			AudioFormat::Wav => "wav",
+			AudioFormat::Flac => "flac",
+		}
+	}
+
+	// Work out the format from the magic bytes at the start of the file
+	pub fn sniff(data: &[u8]) -> Option<Self> {
+		if data.starts_with(b"OggS") {
+			Some(AudioFormat::Vorbis)
+		} else if data.starts_with(b"fLaC") {
+			Some(AudioFormat::Flac)
+		} else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WAVE" {
+			Some(AudioFormat::Wav)
+		} else if data.starts_with(b"ID3") || (data.len() >= 2 && data[0] == 0xFF && data[1] & 0xE0 == 0xE0) {
+			// ID3 tag or an mpeg frame sync
+			Some(AudioFormat::Mp3)
+		} else {
+			None
+		}
+	}
+}
+
+// The compressed bytes of an audio file, shared so decoders can read them without copying
+#[derive(Clone)]
+struct SharedBytes(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedBytes {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+// Audio as it came out of the pack, only decoded once the song gets played
+// The length comes from the headers when it's loaded, since beat timing depends on it
+// Anything broken only shows up when it's played
+#[derive(Clone)]
+pub struct EncodedAudio {
+	data: SharedBytes,
+	format: AudioFormat,
+	duration: Duration,
+}
+
+impl EncodedAudio {
+	// The magic bytes win over the extension, since people rename files
+	pub fn new(data: Vec<u8>, extension: Option<&str>) -> Result<Self> {
+		let format = AudioFormat::sniff(&data)
+			.or_else(|| extension.and_then(AudioFormat::from_extension))
+			.ok_or("Unknown audio format")?;
+		let data = SharedBytes(Arc::new(data));
+
+		let duration = match header_duration(&data, format) {
+			Some(duration) => duration,
+			// Nothing useful in the headers, so count the samples
+			None => count_duration(decode_source(&data, format)?),
+		};
+
+		Ok(EncodedAudio { data, format, duration })
+	}
+
+	pub fn duration(&self) -> Duration {
+		self.duration
+	}
+
+	pub fn format(&self) -> AudioFormat {
+		self.format
+	}
+
+	// The file as it was, for writing it back out
+	pub fn data(&self) -> &[u8] {
+		self.data.as_ref()
+	}
+
+	// Decodes as it's played, the buffer keeps it around for looping
+	pub fn decode(&self) -> Result<AudioData> {
+		Ok(decode_source(&self.data, self.format)?.buffered())
+	}
+
+	// Loudest sample in each of `slices` even pieces, from 0 to 1, for drawing the waveform
+	// Decodes the whole thing, so it takes a moment
+	pub fn peaks(&self, slices: usize) -> Result<Vec<f32>> {
+		let mut peaks = vec![0.0; slices];
+		if slices == 0 {
+			return Ok(peaks);
+		}
+
+		let source = decode_source(&self.data, self.format)?;
+		let samples_per_sec = source.channels() as f64 * source.samples_rate() as f64;
+		let total = (duration_to_secs(self.duration) * samples_per_sec).max(1.0);
+		for (i, sample) in source.enumerate() {
+			let slice = ((i as f64 / total * slices as f64) as usize).min(slices - 1);
+			let sample = (sample as f32 / 32768.0).abs();
+			if sample > peaks[slice] {
+				peaks[slice] = sample;
+			}
+		}
+		Ok(peaks)
+	}
+}
+
+fn decode_source(data: &SharedBytes, format: AudioFormat) -> Result<Box<dyn Source<Item = i16> + Send>> {
+	let cursor = Cursor::new(data.clone());
+	match format {
+		AudioFormat::Mp3 => Ok(Box::new(Mp3Decoder::new(cursor)?) as Box<dyn Source<Item = i16> + Send>),
+		AudioFormat::Vorbis | AudioFormat::Wav | AudioFormat::Flac => {
+			let decoder = Decoder::new(cursor)?;
+			if decoder.channels() == 0 || decoder.samples_rate() == 0 {
+				Err("Bad audio format".into())
+			} else {
+				Ok(Box::new(decoder) as Box<dyn Source<Item = i16> + Send>)
+			}
+		}
+	}
+}
+
+fn header_duration(data: &SharedBytes, format: AudioFormat) -> Option<Duration> {
+	let bytes = data.as_ref();
+	match format {
+		AudioFormat::Mp3 => mp3::header_duration(bytes),
+		AudioFormat::Wav => wav_duration(bytes),
+		AudioFormat::Flac => flac_duration(bytes),
+		AudioFormat::Vorbis => vorbis_duration(bytes),
+	}
+}
+
+fn count_duration<S: Source<Item = i16>>(source: S) -> Duration {
+	let samples_per_sec = source.channels() as u64 * source.samples_rate() as u64;
+	samples_to_duration(source.count() as u64, samples_per_sec)
+}
+
+fn samples_to_duration(samples: u64, samples_per_sec: u64) -> Duration {
+	if samples_per_sec == 0 {
+		return Duration::new(0, 0);
+	}
+	let secs = samples / samples_per_sec;
+	let nanos = (samples % samples_per_sec) * 1_000_000_000 / samples_per_sec;
+	Duration::new(secs, nanos as u32)
+}
+
+fn read_u16(bytes: &[u8], pos: usize) -> Option<u16> {
+	let bytes = bytes.get(pos..pos + 2)?;
+	Some(bytes[0] as u16 | (bytes[1] as u16) << 8)
+}
+
+fn read_u32(bytes: &[u8], pos: usize) -> Option<u32> {
+	Some(read_u16(bytes, pos)? as u32 | (read_u16(bytes, pos + 2)? as u32) << 16)
+}
+
+fn read_u64(bytes: &[u8], pos: usize) -> Option<u64> {
+	Some(read_u32(bytes, pos)? as u64 | (read_u32(bytes, pos + 4)? as u64) << 32)
+}
+
+// Size of the data chunk over the size of a frame from the fmt chunk
+fn wav_duration(bytes: &[u8]) -> Option<Duration> {
+	let mut pos = 12;
+	let mut format = None;
+	while pos + 8 <= bytes.len() {
+		let size = read_u32(bytes, pos + 4)? as usize;
+		let body = pos + 8;
+		match &bytes[pos..pos + 4] {
+			b"fmt " => format = Some((read_u32(bytes, body + 4)?, read_u16(bytes, body + 12)?)),
+			b"data" => {
+				let (samples_rate, block_align) = format?;
+				if block_align == 0 {
+					return None;
+				}
+				let size = size.min(bytes.len() - body);
+				return Some(samples_to_duration((size / block_align as usize) as u64, samples_rate as u64));
+			}
+			_ => {}
+		}
+		// Chunks are padded to an even length
+		pos = body + size + (size & 1);
+	}
+	None
+}
+
+// STREAMINFO is always the first block, and has the total number of samples if the encoder knew it
+fn flac_duration(bytes: &[u8]) -> Option<Duration> {
+	let info = bytes.get(8..8 + 18)?;
+	let samples_rate = (info[10] as u32) << 12 | (info[11] as u32) << 4 | (info[12] as u32) >> 4;
+	let total = ((info[13] & 0x0F) as u64) << 32
+		| (info[14] as u64) << 24
+		| (info[15] as u64) << 16
+		| (info[16] as u64) << 8
+		| info[17] as u64;
+	if total == 0 || samples_rate == 0 {
+		return None;
+	}
+	Some(samples_to_duration(total, samples_rate as u64))
+}
+
+// The granule position of the last page is the number of samples per channel
+fn vorbis_duration(bytes: &[u8]) -> Option<Duration> {
+	let ident = bytes.windows(7).take(512).position(|window| window == b"\x01vorbis")?;
+	let samples_rate = read_u32(bytes, ident + 12)?;
+
+	let last_page = (0..bytes.len().saturating_sub(14))
+		.rev()
+		.find(|&pos| &bytes[pos..pos + 4] == b"OggS")?;
+	let granule = read_u64(bytes, last_page + 6)?;
+	if granule == 0 || granule == u64::MAX || samples_rate == 0 {
+		return None;
+	}
+	Some(samples_to_duration(granule, samples_rate as u64))
+}
+
+// Counts the samples that have been pulled out of a source, so the visuals can follow
+// what's actually been played instead of a separate clock
+pub struct Counted<S> {
+	source: S,
+	samples: Arc<AtomicUsize>,
+}
+
+impl<S: Source<Item = i16>> Counted<S> {
+	pub fn new(source: S) -> (Self, SampleCounter) {
+		let samples = Arc::new(AtomicUsize::new(0));
+		let counter = SampleCounter {
+			samples: samples.clone(),
+			channels: source.channels(),
+			samples_rate: source.samples_rate(),
+		};
+
+		(Counted { source, samples }, counter)
+	}
+}
+
+impl<S: Source<Item = i16>> Iterator for Counted<S> {
+	type Item = i16;
+
+	#[inline]
+	fn next(&mut self) -> Option<i16> {
+		let sample = self.source.next();
+		if sample.is_some() {
+			self.samples.fetch_add(1, Ordering::Relaxed);
+		}
+		sample
+	}
+
+	#[inline]
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.source.size_hint()
+	}
+}
+
+impl<S: Source<Item = i16>> Source for Counted<S> {
+	#[inline]
+	fn current_frame_len(&self) -> Option<usize> {
+		self.source.current_frame_len()
+	}
+
+	#[inline]
+	fn channels(&self) -> u16 {
+		self.source.channels()
+	}
+
+	#[inline]
+	fn samples_rate(&self) -> u32 {
+		self.source.samples_rate()
+	}
+
+	#[inline]
+	fn total_duration(&self) -> Option<Duration> {
+		self.source.total_duration()
+	}
+}
+
+// The other end of a Counted source
+#[derive(Clone)]
+pub struct SampleCounter {
+	samples: Arc<AtomicUsize>,
+	channels: u16,
+	samples_rate: u32,
+}
+
+impl SampleCounter {
+	pub fn position(&self) -> Duration {
+		let samples_per_sec = self.channels as u64 * self.samples_rate as u64;
+		samples_to_duration(self.samples.load(Ordering::Relaxed) as u64, samples_per_sec)
+	}
+}
+
+// Throws away the start of a source, in whole frames so the channels stay lined up
+pub fn skip_duration<S: Source<Item = i16>>(mut source: S, skip: Duration) -> S {
+	let frames = (duration_to_secs(skip) * source.samples_rate() as f64) as usize;
+	for _ in 0..frames * source.channels() as usize {
+		if source.next().is_none() {
+			break;
+		}
+	}
+	source
+}
+
+// Position in a song made up of a buildup followed by the loop
+#[derive(Clone)]
+pub enum PlaybackClock {
+	// Where playback was started from, plus whatever's been played since
+	Samples(Duration, Vec<SampleCounter>),
+	// Nothing is actually playing, so just go by the clock
+	Timer(Instant, Duration),
+	// A stopped Timer
+	Paused(Duration),
+}
+
+impl PlaybackClock {
+	pub fn position(&self) -> Duration {
+		match *self {
+			PlaybackClock::Samples(start, ref counters) => counters
+				.iter()
+				.fold(start, |acc, counter| acc + counter.position()),
+			PlaybackClock::Timer(start, offset) => offset + clock::elapsed(start),
+			PlaybackClock::Paused(position) => position,
+		}
+	}
+
+	// Samples stop by themselves when the sink is paused
+	pub fn pause(&mut self) {
+		if let PlaybackClock::Timer(..) = *self {
+			*self = PlaybackClock::Paused(self.position());
+		}
+	}
+
+	pub fn resume(&mut self) {
+		if let PlaybackClock::Paused(position) = *self {
+			*self = PlaybackClock::Timer(clock::now(), position);
+		}
+	}
+
+	pub fn is_paused(&self) -> bool {
+		matches!(*self, PlaybackClock::Paused(_))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::{flac_duration, vorbis_duration, wav_duration};
+
+	fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+		let size = body.len() as u32;
+		let mut chunk = id.to_vec();
+		chunk.extend_from_slice(&[size as u8, (size >> 8) as u8, (size >> 16) as u8, (size >> 24) as u8]);
+		chunk.extend_from_slice(body);
+		chunk
+	}
+
+	// Mono 16 bit, so two bytes to a frame
+	fn fmt_chunk(samples_rate: u32, block_align: u16) -> Vec<u8> {
+		let rate = [samples_rate as u8, (samples_rate >> 8) as u8, (samples_rate >> 16) as u8, (samples_rate >> 24) as u8];
+		let mut body = vec![1, 0, 1, 0];
+		body.extend_from_slice(&rate);
+		body.extend_from_slice(&[0, 0, 0, 0]);
+		body.extend_from_slice(&[block_align as u8, (block_align >> 8) as u8, 16, 0]);
+		chunk(b"fmt ", &body)
+	}
+
+	fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+		let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+		for chunk in chunks {
+			wav.extend_from_slice(chunk);
+		}
+		wav
+	}
+
+	#[test]
+	fn wav_length() {
+		let wav = wav(&[fmt_chunk(100, 2), chunk(b"data", &[0; 100])]);
+		assert_eq!(wav_duration(&wav), Some(Duration::from_millis(500)));
+	}
+
+	#[test]
+	fn wav_without_fmt() {
+		let wav = wav(&[chunk(b"data", &[0; 100])]);
+		assert_eq!(wav_duration(&wav), None);
+	}
+
+	#[test]
+	fn wav_odd_chunk_padding() {
+		// Three bytes and a pad byte, which isn't counted in the size
+		let mut list = chunk(b"LIST", &[1, 2, 3]);
+		list.push(0);
+		let wav = wav(&[list, fmt_chunk(100, 2), chunk(b"data", &[0; 100])]);
+		assert_eq!(wav_duration(&wav), Some(Duration::from_millis(500)));
+	}
+
+	#[test]
+	fn wav_zero_block_align() {
+		let wav = wav(&[fmt_chunk(100, 0), chunk(b"data", &[0; 100])]);
+		assert_eq!(wav_duration(&wav), None);
+	}
+
+	#[test]
+	fn wav_data_size_past_the_end() {
+		// Streamed files sometimes leave the size at the maximum
+		let mut wav = wav(&[fmt_chunk(100, 2), chunk(b"data", &[0; 100])]);
+		let size = wav.len() - 104;
+		for byte in &mut wav[size..size + 4] {
+			*byte = 0xFF;
+		}
+		assert_eq!(wav_duration(&wav), Some(Duration::from_millis(500)));
+	}
+
+	// 20 bits of sample rate, 3 of channels, 5 of bits per sample, then 36 of total samples
+	fn flac(samples_rate: u32, total: u64) -> Vec<u8> {
+		let mut flac = b"fLaC".to_vec();
+		flac.extend_from_slice(&[0x80, 0, 0, 34]);
+		let mut info = [0; 34];
+		info[10] = (samples_rate >> 12) as u8;
+		info[11] = (samples_rate >> 4) as u8;
+		info[12] = (samples_rate << 4) as u8 | 1 << 1;
+		info[13] = 15 << 4 | (total >> 32) as u8 & 0x0F;
+		info[14] = (total >> 24) as u8;
+		info[15] = (total >> 16) as u8;
+		info[16] = (total >> 8) as u8;
+		info[17] = total as u8;
+		flac.extend_from_slice(&info);
+		flac
+	}
+
+	#[test]
+	fn flac_length() {
+		assert_eq!(flac_duration(&flac(44100, 88200)), Some(Duration::from_secs(2)));
+	}
+
+	#[test]
+	fn flac_unknown_total() {
+		assert_eq!(flac_duration(&flac(44100, 0)), None);
+	}
+
+	#[test]
+	fn flac_zero_samples_rate() {
+		assert_eq!(flac_duration(&flac(0, 88200)), None);
+	}
+
+	#[test]
+	fn flac_truncated() {
+		assert_eq!(flac_duration(&flac(44100, 88200)[..20]), None);
+	}
+
+	fn ogg_page(granule: u64, body: &[u8]) -> Vec<u8> {
+		let mut page = b"OggS\0\0".to_vec();
+		for i in 0..8 {
+			page.push((granule >> (i * 8)) as u8);
+		}
+		// Serial, sequence number and checksum aren't looked at
+		page.extend_from_slice(&[0; 12]);
+		page.extend_from_slice(&[1, body.len() as u8]);
+		page.extend_from_slice(body);
+		page
+	}
+
+	fn vorbis_ident(samples_rate: u32) -> Vec<u8> {
+		let mut ident = b"\x01vorbis\0\0\0\0\x02".to_vec();
+		ident.extend_from_slice(&[samples_rate as u8, (samples_rate >> 8) as u8, (samples_rate >> 16) as u8, (samples_rate >> 24) as u8]);
+		ident
+	}
+
+	#[test]
+	fn vorbis_length() {
+		let mut ogg = ogg_page(0, &vorbis_ident(100));
+		ogg.extend_from_slice(&ogg_page(150, b"audio"));
+		assert_eq!(vorbis_duration(&ogg), Some(Duration::from_millis(1500)));
+	}
+
+	#[test]
+	fn vorbis_zero_granule() {
+		let ogg = ogg_page(0, &vorbis_ident(100));
+		assert_eq!(vorbis_duration(&ogg), None);
+	}
+
+	#[test]
+	fn vorbis_unfinished_granule() {
+		// All ones means no packet finished on the page
+		let mut ogg = ogg_page(0, &vorbis_ident(100));
+		ogg.extend_from_slice(&ogg_page(u64::MAX, b"audio"));
+		assert_eq!(vorbis_duration(&ogg), None);
+	}
+
+	#[test]
+	fn vorbis_without_ident() {
+		let mut ogg = ogg_page(0, b"not vorbis");
+		ogg.extend_from_slice(&ogg_page(150, b"audio"));
+		assert_eq!(vorbis_duration(&ogg), None);
+	}
+}
@@ -0,0 +1,128 @@
+// What each character in a rhythm does
+// Follows the beat glossary from 0x40-web, so packs made for it look the same here
+// Not in here yet: the short blur variants and the tap-based fades, see TODO.md
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColourChange {
+	None,
+	Random,
+	// Fade to a random colour over the time until the next beat
+	Fade,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BlurDirection {
+	Horizontal,
+	Vertical,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Blackout {
+	// Leave whatever blackout is going on
+	Keep,
+	Clear,
+	// Fade out to black/white
+	Black,
+	White,
+	// Solid black/white until the next beat
+	ShortBlack,
+	ShortWhite,
+	// Straight to black/white without the fade
+	InstantBlack,
+	InstantWhite,
+	// White that fades away over the beat
+	Flash,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Trippy {
+	In,
+	Out,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Slice {
+	Horizontal,
+	Vertical,
+	Both,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Direction {
+	Left,
+	Right,
+	Up,
+	Down,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BeatEffect {
+	pub colour: ColourChange,
+	pub image: bool,
+	pub blur: Option<BlurDirection>,
+	pub blackout: Blackout,
+	pub invert: bool,
+	pub trippy: Option<Trippy>,
+	pub slice: Option<Slice>,
+	pub shutter: Option<Direction>,
+}
+
+const NOTHING: BeatEffect = BeatEffect {
+	colour: ColourChange::None,
+	image: false,
+	blur: None,
+	blackout: Blackout::Clear,
+	invert: false,
+	trippy: None,
+	slice: None,
+	shutter: None,
+};
+
+// Character, what it does, description for help text
+pub static BEATS: [(char, BeatEffect, &str); 33] = [
+	('.', BeatEffect { blackout: Blackout::Keep, ..NOTHING }, "Nothing"),
+	('x', BeatEffect { colour: ColourChange::Random, image: true, blur: Some(BlurDirection::Vertical), ..NOTHING }, "Vertical blur (snare)"),
+	('o', BeatEffect { colour: ColourChange::Random, image: true, blur: Some(BlurDirection::Horizontal), ..NOTHING }, "Horizontal blur (bass)"),
+	('-', BeatEffect { colour: ColourChange::Random, image: true, ..NOTHING }, "No blur"),
+	('+', BeatEffect { blur: Some(BlurDirection::Horizontal), blackout: Blackout::Black, ..NOTHING }, "Blackout"),
+	('¤', BeatEffect { blur: Some(BlurDirection::Horizontal), blackout: Blackout::White, ..NOTHING }, "Whiteout"),
+	('|', BeatEffect { colour: ColourChange::Random, image: true, blackout: Blackout::ShortBlack, ..NOTHING }, "Short blackout"),
+	('!', BeatEffect { colour: ColourChange::Random, image: true, blackout: Blackout::ShortWhite, ..NOTHING }, "Short whiteout"),
+	('┊', BeatEffect { blur: Some(BlurDirection::Horizontal), blackout: Blackout::InstantBlack, ..NOTHING }, "Instant blackout"),
+	('¦', BeatEffect { blur: Some(BlurDirection::Horizontal), blackout: Blackout::InstantWhite, ..NOTHING }, "Instant whiteout"),
+	('≡', BeatEffect { blackout: Blackout::Flash, ..NOTHING }, "Flash"),
+	(':', BeatEffect { colour: ColourChange::Random, ..NOTHING }, "Colour only"),
+	('*', BeatEffect { image: true, ..NOTHING }, "Image only"),
+	('X', BeatEffect { blur: Some(BlurDirection::Vertical), ..NOTHING }, "Vertical blur only"),
+	('O', BeatEffect { blur: Some(BlurDirection::Horizontal), ..NOTHING }, "Horizontal blur only"),
+	(')', BeatEffect { colour: ColourChange::Random, image: true, trippy: Some(Trippy::In), ..NOTHING }, "Trippy circle in and change image"),
+	('(', BeatEffect { colour: ColourChange::Random, image: true, trippy: Some(Trippy::Out), ..NOTHING }, "Trippy circle out and change image"),
+	('>', BeatEffect { colour: ColourChange::Random, trippy: Some(Trippy::In), ..NOTHING }, "Trippy circle in"),
+	('<', BeatEffect { colour: ColourChange::Random, trippy: Some(Trippy::Out), ..NOTHING }, "Trippy circle out"),
+	('~', BeatEffect { colour: ColourChange::Fade, ..NOTHING }, "Fade colour"),
+	('=', BeatEffect { colour: ColourChange::Fade, image: true, ..NOTHING }, "Fade and change image"),
+	('i', BeatEffect { invert: true, ..NOTHING }, "Invert all colours"),
+	('I', BeatEffect { image: true, invert: true, ..NOTHING }, "Invert and change image"),
+	('s', BeatEffect { colour: ColourChange::Random, slice: Some(Slice::Horizontal), ..NOTHING }, "Horizontal slice"),
+	('S', BeatEffect { colour: ColourChange::Random, image: true, slice: Some(Slice::Horizontal), ..NOTHING }, "Horizontal slice and change image"),
+	('v', BeatEffect { colour: ColourChange::Random, slice: Some(Slice::Vertical), ..NOTHING }, "Vertical slice"),
+	('V', BeatEffect { colour: ColourChange::Random, image: true, slice: Some(Slice::Vertical), ..NOTHING }, "Vertical slice and change image"),
+	('#', BeatEffect { colour: ColourChange::Random, slice: Some(Slice::Both), ..NOTHING }, "Double slice"),
+	('@', BeatEffect { colour: ColourChange::Random, image: true, slice: Some(Slice::Both), ..NOTHING }, "Double slice and change image"),
+	('←', BeatEffect { colour: ColourChange::Random, image: true, shutter: Some(Direction::Left), ..NOTHING }, "Shutter left"),
+	('↓', BeatEffect { colour: ColourChange::Random, image: true, shutter: Some(Direction::Down), ..NOTHING }, "Shutter down"),
+	('↑', BeatEffect { colour: ColourChange::Random, image: true, shutter: Some(Direction::Up), ..NOTHING }, "Shutter up"),
+	('→', BeatEffect { colour: ColourChange::Random, image: true, shutter: Some(Direction::Right), ..NOTHING }, "Shutter right"),
+];
+
+pub fn effect(beat: char) -> Option<BeatEffect> {
+	BEATS
+		.iter()
+		.find(|&&(ch, _, _)| ch == beat)
+		.map(|&(_, effect, _)| effect)
+}
+
+// Beats that don't do anything, for working out how long until the next one
+pub fn is_empty(beat: char) -> bool {
+	beat == '.'
+}
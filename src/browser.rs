@@ -0,0 +1,235 @@
+// A list of every song or image to pick from, narrowed down by typing
+// Up/Down/Page Up/Page Down move, Enter picks, Escape closes
+
+use sdl2::pixels::Color as Colour;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use sdl2::ttf::Font;
+
+use glyphs::GlyphAtlas;
+use images::ImageManager;
+use loader::PackInfo;
+use packs::Packs;
+use songs::SongManager;
+use ui::{centred_layout, scale_rect, TextUi};
+use Result;
+
+// In layout coordinates
+const BROWSER_RECT: (i32, i32, u32, u32) = (160, 60, 960, 600);
+const PADDING: i32 = 12;
+const LINE_HEIGHT: i32 = 14;
+// Title and search take the first two lines, plus a gap
+const HEADER_LINES: i32 = 3;
+const ROWS: usize = 37;
+// Anything longer gets cut off instead of running off the panel
+const MAX_CHARS: usize = 76;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BrowserKind {
+	Songs,
+	Images,
+}
+
+// Title, source and pack
+pub fn song_browser<'a, Target>(
+	song_manager: &SongManager,
+	packs: &Packs,
+	font: &'a Font<'a, 'static>,
+	texture_creator: &'a TextureCreator<Target>,
+) -> Result<Browser<'a, Target>> {
+	let entries = song_manager
+		.songs()
+		.iter()
+		.enumerate()
+		.map(|(index, song)| {
+			let pack = packs.info(song.pack()).map_or("?", PackInfo::name);
+			let line = match song.source {
+				Some(ref source) => format!("{} - {} [{}]", song.title, source, pack),
+				None => format!("{} [{}]", song.title, pack),
+			};
+			(index, line)
+		})
+		.collect();
+	Browser::new(BrowserKind::Songs, "Songs", entries, font, texture_creator)
+}
+
+// Full name and source
+pub fn image_browser<'a, Target>(
+	image_manager: &ImageManager<Target>,
+	font: &'a Font<'a, 'static>,
+	texture_creator: &'a TextureCreator<Target>,
+) -> Result<Browser<'a, Target>> {
+	let entries = image_manager
+		.images()
+		.iter()
+		.enumerate()
+		.map(|(index, image)| {
+			let name = image.fullname().unwrap_or_else(|| image.name());
+			let line = match image.source() {
+				Some(source) => format!("{} - {}", name, source),
+				None => name.to_owned(),
+			};
+			(index, line)
+		})
+		.collect();
+	Browser::new(BrowserKind::Images, "Images", entries, font, texture_creator)
+}
+
+pub struct Browser<'a, Target: 'a> {
+	kind: BrowserKind,
+	title: String,
+	// Index into the songs or images, and the line that's shown and searched
+	entries: Vec<(usize, String)>,
+
+	filter: String,
+	// Positions in entries that match the filter
+	matches: Vec<usize>,
+	// Position in matches
+	selected: usize,
+	// First match shown
+	scroll: usize,
+
+	title_text: TextUi,
+	filter_text: TextUi,
+	rows: Vec<TextUi>,
+	atlas: GlyphAtlas<'a, Target>,
+}
+
+impl<'a, Target> Browser<'a, Target> {
+	pub fn new(
+		kind: BrowserKind,
+		title: &str,
+		entries: Vec<(usize, String)>,
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Result<Self> {
+		let mut atlas = GlyphAtlas::new(font, texture_creator)?;
+
+		let (x, y) = (BROWSER_RECT.0 + PADDING, BROWSER_RECT.1 + PADDING);
+		let mut title_text = TextUi::create("", &mut atlas)?;
+		title_text.set_pos(x, y);
+		let mut filter_text = TextUi::create("", &mut atlas)?;
+		filter_text.set_pos(x, y + LINE_HEIGHT);
+
+		let mut rows = Vec::with_capacity(ROWS);
+		for row in 0..ROWS {
+			let mut text = TextUi::create("", &mut atlas)?;
+			text.set_pos(x, y + (HEADER_LINES + row as i32) * LINE_HEIGHT);
+			rows.push(text);
+		}
+
+		let mut browser = Browser {
+			kind,
+			title: title.to_uppercase(),
+			entries: entries
+				.into_iter()
+				.map(|(index, line)| (index, line.to_uppercase().chars().take(MAX_CHARS).collect()))
+				.collect(),
+
+			filter: String::new(),
+			matches: Vec::new(),
+			selected: 0,
+			scroll: 0,
+
+			title_text,
+			filter_text,
+			rows,
+			atlas,
+		};
+		browser.update_matches();
+
+		Ok(browser)
+	}
+
+	pub fn kind(&self) -> BrowserKind {
+		self.kind
+	}
+
+	pub fn type_text(&mut self, text: &str) {
+		self.filter.push_str(&text.to_uppercase());
+		self.update_matches();
+	}
+
+	pub fn backspace(&mut self) {
+		self.filter.pop();
+		self.update_matches();
+	}
+
+	// Every word in the filter has to be in there somewhere
+	fn update_matches(&mut self) {
+		let words: Vec<&str> = self.filter.split_whitespace().collect();
+		self.matches = self.entries
+			.iter()
+			.enumerate()
+			.filter(|(_, (_, line))| words.iter().all(|word| line.contains(word)))
+			.map(|(position, _)| position)
+			.collect();
+		self.selected = 0;
+		self.scroll = 0;
+	}
+
+	pub fn move_selection(&mut self, change: i32) {
+		if self.matches.is_empty() {
+			return;
+		}
+		let last = self.matches.len() as i32 - 1;
+		self.selected = (self.selected as i32 + change).max(0).min(last) as usize;
+
+		if self.selected < self.scroll {
+			self.scroll = self.selected;
+		} else if self.selected >= self.scroll + ROWS {
+			self.scroll = self.selected + 1 - ROWS;
+		}
+	}
+
+	pub fn page_size(&self) -> i32 {
+		ROWS as i32
+	}
+
+	// Index of the song or image to go to
+	pub fn selected(&self) -> Option<usize> {
+		self.matches
+			.get(self.selected)
+			.map(|&position| self.entries[position].0)
+	}
+
+	pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		let title = format!("{} ({}/{})", self.title, self.matches.len(), self.entries.len());
+		self.title_text.set_text(title, &mut self.atlas)?;
+		self.filter_text.set_text(format!("SEARCH: {}_", self.filter), &mut self.atlas)?;
+		let (entries, matches) = (&self.entries, &self.matches);
+		for (row, text) in self.rows.iter_mut().enumerate() {
+			let line = matches
+				.get(self.scroll + row)
+				.map_or("", |&position| entries[position].1.as_ref());
+			text.set_text(line, &mut self.atlas)?;
+		}
+
+		let (width, height) = canvas.output_size()?;
+		let (scale, origin) = centred_layout(width, height);
+
+		let (x, y, w, h) = BROWSER_RECT;
+		canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xE0));
+		canvas.fill_rect(scale_rect(Rect::new(x, y, w, h), scale, origin))?;
+
+		if !self.matches.is_empty() {
+			let row = (self.selected - self.scroll) as i32;
+			let highlight = Rect::new(
+				x + PADDING / 2,
+				y + PADDING + (HEADER_LINES + row) * LINE_HEIGHT - 1,
+				w - PADDING as u32,
+				LINE_HEIGHT as u32,
+			);
+			canvas.set_draw_color(Colour::RGBA(0x00, 0x00, 0x00, 0x40));
+			canvas.fill_rect(scale_rect(highlight, scale, origin))?;
+		}
+
+		self.title_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.filter_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		for text in &self.rows {
+			text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		}
+
+		Ok(())
+	}
+}
@@ -0,0 +1,190 @@
+// hues check - loads packs the same way as playing them, but without a window, and lists what's wrong
+// One problem per line, tab separated: severity, kind, pack, file, position, message
+// Exits with 1 if there were any errors, warnings are fine
+
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::channel;
+
+use sdl2;
+
+use archive;
+use beats;
+use config::Config;
+use loader::{self, LoadError, LoadStatus, ResPack, Skipped};
+use Result;
+
+// A buildup this far from a power of two times the loop's beats gets a warning
+const BUILDUP_TOLERANCE: f64 = 0.1;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Severity {
+	Error,
+	Warning,
+}
+
+struct Problem {
+	severity: Severity,
+	kind: &'static str,
+	pack: String,
+	file: Option<String>,
+	position: Option<String>,
+	message: String,
+}
+
+impl Problem {
+	fn new<T: Into<String>>(severity: Severity, kind: &'static str, pack: &str, message: T) -> Self {
+		Problem {
+			severity,
+			kind,
+			pack: pack.to_owned(),
+			file: None,
+			position: None,
+			message: message.into(),
+		}
+	}
+
+	fn from_load_error(error: LoadError) -> Self {
+		let (severity, kind) = match error.skipped {
+			Skipped::Pack => (Severity::Error, "pack"),
+			Skipped::File(_) => (Severity::Error, "file"),
+			Skipped::Song(_) => (Severity::Error, "song"),
+			Skipped::Image(_) => (Severity::Error, "image"),
+			Skipped::Element(_) => (Severity::Warning, "element"),
+			Skipped::Unused(_) => (Severity::Warning, "unused"),
+		};
+		let message = match error.skipped {
+			Skipped::Pack => error.message,
+			Skipped::File(ref name) | Skipped::Song(ref name) | Skipped::Image(ref name) | Skipped::Unused(ref name) => {
+				format!("{}: {}", name, error.message)
+			}
+			Skipped::Element(ref name) => format!("<{}>: {}", name, error.message),
+		};
+		Problem {
+			severity,
+			kind,
+			pack: error.pack,
+			file: error.file,
+			position: error.position.map(|position| position.to_string()),
+			message,
+		}
+	}
+
+	fn print(&self) {
+		let severity = match self.severity {
+			Severity::Error => "error",
+			Severity::Warning => "warning",
+		};
+		let fields = [
+			severity,
+			self.kind,
+			&self.pack,
+			self.file.as_ref().map_or("-", String::as_ref),
+			self.position.as_ref().map_or("-", String::as_ref),
+			&self.message,
+		];
+		let fields: Vec<String> = fields.iter().map(|field| field.replace(&['\t', '\n'][..], " ")).collect();
+		println!("{}", fields.join("\t"));
+	}
+}
+
+// Whether there were any errors
+pub fn run(config: &Config) -> Result<bool> {
+	sdl2::image::init(sdl2::image::INIT_PNG | sdl2::image::INIT_JPG | sdl2::image::INIT_WEBP)?;
+
+	let paths = config.respack_paths();
+	if paths.is_empty() {
+		return Err("No packs to check".into());
+	}
+
+	let mut problems = Vec::new();
+	// Names can only be looked up if they're unique, so the first pack with each one wins
+	let mut songs: HashMap<String, String> = HashMap::new();
+	let mut images: HashMap<String, String> = HashMap::new();
+
+	for path in paths {
+		let (tx, rx) = channel();
+		loader::load_respack(&path, tx)?;
+		for status in rx.iter() {
+			match status {
+				LoadStatus::Failed(error) => problems.push(Problem::from_load_error(error)),
+				LoadStatus::Done(pack) => {
+					let name = pack.info.name();
+					check_songs(&pack, &mut problems);
+					check_duplicates("Song", pack.songs.iter().map(|song| song.name()), name, &mut songs, &mut problems);
+					check_duplicates("Image", pack.images.iter().map(|image| image.name.as_str()), name, &mut images, &mut problems);
+				}
+				_ => {}
+			}
+		}
+		eprintln!("Checked {}", archive::pack_name(&path));
+	}
+
+	for problem in &problems {
+		problem.print();
+	}
+
+	let errors = problems.iter().filter(|problem| problem.severity == Severity::Error).count();
+	eprintln!("{} errors, {} warnings", errors, problems.len() - errors);
+	Ok(errors > 0)
+}
+
+fn check_songs(pack: &ResPack, problems: &mut Vec<Problem>) {
+	let pack_name = pack.info.name();
+	for song in &pack.songs {
+		for (rhythm, which) in [(&song.rhythm, "rhythm"), (&song.buildup_rhythm, "buildup rhythm")].iter() {
+			let mut unknown: Vec<char> = rhythm.iter().cloned().filter(|&beat| beats::effect(beat).is_none()).collect();
+			unknown.sort();
+			unknown.dedup();
+			if !unknown.is_empty() {
+				let unknown: String = unknown.into_iter().collect();
+				let message = format!("{}: Unknown beats in {}: {}", song.title, which, unknown);
+				problems.push(Problem::new(Severity::Error, "beat", pack_name, message));
+			}
+		}
+
+		// Loading only reads the headers, so this is where broken audio shows up
+		let buildup = song.buildup().map(|(_, audio)| audio);
+		for (audio, which) in [(Some(song.loop_audio()), "loop"), (buildup, "buildup")].iter() {
+			if let Some(Err(err)) = audio.map(|audio| audio.decode()) {
+				let message = format!("{}: Could not decode the {}: {}", song.title, which, err);
+				problems.push(Problem::new(Severity::Error, "audio", pack_name, message));
+			}
+		}
+
+		// A single beat is what gets filled in when there isn't a buildup rhythm
+		let beats = song.buildup_rhythm.len();
+		if let Some(expected) = song.buildup_length_in_beats() {
+			let ratio = (expected / beats as f64).log2();
+			if beats > 1 && (ratio - ratio.round()).abs() > BUILDUP_TOLERANCE {
+				let message = format!(
+					"{}: Buildup rhythm has {} beats, but the buildup is about {:.1} beats long",
+					song.title, beats, expected
+				);
+				problems.push(Problem::new(Severity::Warning, "buildup", pack_name, message));
+			}
+		}
+	}
+}
+
+fn check_duplicates<'a, I: Iterator<Item = &'a str>>(
+	what: &str,
+	names: I,
+	pack: &str,
+	seen: &mut HashMap<String, String>,
+	problems: &mut Vec<Problem>,
+) {
+	let mut in_pack = HashSet::new();
+	for name in names {
+		if !in_pack.insert(name) {
+			let message = format!("{} {} is in this pack more than once", what, name);
+			problems.push(Problem::new(Severity::Warning, "duplicate", pack, message));
+			continue;
+		}
+		if let Some(first) = seen.get(name) {
+			let message = format!("{} {} is also in {}", what, name, first);
+			problems.push(Problem::new(Severity::Warning, "duplicate", pack, message));
+			continue;
+		}
+		seen.insert(name.to_owned(), pack.to_owned());
+	}
+}
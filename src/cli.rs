@@ -0,0 +1,188 @@
+// Command line arguments, which get merged over the config file
+//
+// hues [OPTIONS] [RESPACK...]
+// hues render [OPTIONS] [RENDER OPTIONS] [RESPACK...]
+// hues check [RESPACK...]
+// hues repack RESPACK OUTPUT
+
+use std::path::PathBuf;
+
+use render::{Output, RenderOptions};
+use songs::BuildupMode;
+use Result;
+
+pub const USAGE: &str = "\
+Usage: hues [render] [OPTIONS] [RESPACK...]
+       hues check [RESPACK...]
+       hues repack RESPACK OUTPUT
+
+Respacks can be paths or the names of packs in respacks/
+check lists problems in the packs, one per line, and fails if any are errors
+repack writes a pack out again as OUTPUT, a .zip or a folder, and checks it loads the same
+
+Options:
+    --config PATH       Config file to use (default config.toml)
+    --song NAME         Song to start with
+    --image NAME        Image to start with
+    --size WxH          Window size
+    --fullscreen        Start fullscreen
+    --windowed          Start in a window
+    --full-auto         Change images on the beat
+    --normal            Keep the same image
+    --volume VOL        Volume from 0.0 to 1.0
+    --seed N            Seed for the random colours, images and songs
+    --font PATH         Font for the UI text
+    --buildup MODE      Play buildups always, once or never
+    --keys              Print the key bindings as a [keys] table for the config
+    --help              Show this
+
+Render options:
+    --fps N             Frames per second (default 30)
+    --length SECS       How much to render (default the buildup and one loop)
+    --out DIR           Save frames as png files in DIR
+    --raw               Write raw RGBA frames to stdout
+";
+
+pub enum Command {
+	Play,
+	Render(RenderOptions),
+	Check,
+	Repack(PathBuf),
+	Keys,
+	Help,
+}
+
+pub struct Args {
+	pub command: Command,
+	pub config: Option<PathBuf>,
+
+	pub respacks: Vec<String>,
+	pub song: Option<String>,
+	pub image: Option<String>,
+	pub size: Option<(u32, u32)>,
+	pub fullscreen: Option<bool>,
+	pub full_auto: Option<bool>,
+	pub volume: Option<f32>,
+	pub seed: Option<u64>,
+	pub font: Option<String>,
+	pub buildup: Option<String>,
+}
+
+impl Args {
+	// Without the program name
+	pub fn parse(args: &[String]) -> Result<Self> {
+		let mut parsed = Args {
+			command: Command::Play,
+			config: None,
+
+			respacks: Vec::new(),
+			song: None,
+			image: None,
+			size: None,
+			fullscreen: None,
+			full_auto: None,
+			volume: None,
+			seed: None,
+			font: None,
+			buildup: None,
+		};
+
+		let mut args = args.iter().peekable();
+
+		let mut render = if args.peek().is_some_and(|arg| *arg == "render") {
+			args.next();
+			Some(RenderOptions::default())
+		} else {
+			None
+		};
+		if args.peek().is_some_and(|arg| *arg == "check") {
+			args.next();
+			parsed.command = Command::Check;
+		}
+		let repack = args.peek().is_some_and(|arg| *arg == "repack");
+		if repack {
+			args.next();
+		}
+		let mut output = None;
+
+		while let Some(arg) = args.next() {
+			if !arg.starts_with("--") {
+				parsed.respacks.push(arg.clone());
+				continue;
+			}
+
+			let mut value = || args.next().ok_or_else(|| format!("Missing value for {}", arg));
+			match (arg.as_ref(), render.as_mut()) {
+				("--config", _) => parsed.config = Some(PathBuf::from(value()?)),
+				("--song", _) => parsed.song = Some(value()?.clone()),
+				("--image", _) => parsed.image = Some(value()?.clone()),
+				("--size", _) => parsed.size = Some(parse_size(value()?).ok_or("Size should look like 1280x720")?),
+				("--fullscreen", _) => parsed.fullscreen = Some(true),
+				("--windowed", _) => parsed.fullscreen = Some(false),
+				("--full-auto", _) => parsed.full_auto = Some(true),
+				("--normal", _) => parsed.full_auto = Some(false),
+				("--volume", _) => {
+					let volume = value()?.parse().map_err(|_| "Bad volume")?;
+					parsed.volume = Some(check_volume(volume)?);
+				}
+				("--seed", _) => parsed.seed = Some(value()?.parse().map_err(|_| "Bad seed")?),
+				("--font", _) => parsed.font = Some(value()?.clone()),
+				("--buildup", _) => {
+					let mode = value()?;
+					BuildupMode::from_str(mode).ok_or("Buildup should be always, once or never")?;
+					parsed.buildup = Some(mode.clone());
+				}
+				("--keys", None) => parsed.command = Command::Keys,
+				("--help", _) => parsed.command = Command::Help,
+
+				("--fps", Some(render)) => {
+					render.fps = value()?.parse().map_err(|_| "Bad fps")?;
+					if render.fps == 0 {
+						return Err("Bad fps".into());
+					}
+				}
+				("--length", Some(render)) => render.length = Some(value()?.parse().map_err(|_| "Bad length")?),
+				("--out", Some(_)) => output = Some(Output::Frames(PathBuf::from(value()?))),
+				("--raw", Some(_)) => output = Some(Output::Raw),
+
+				_ => return Err(format!("Unknown option {}", arg).into()),
+			}
+		}
+
+		match parsed.command {
+			Command::Help | Command::Keys => return Ok(parsed),
+			_ => {}
+		}
+
+		// The last path is where it goes
+		if repack {
+			let output = parsed.respacks.pop().ok_or("Need a pack and somewhere to write it")?;
+			parsed.command = Command::Repack(PathBuf::from(output));
+		}
+
+		if let Some(mut render) = render {
+			render.output = output.ok_or("Need either --out DIR or --raw")?;
+			parsed.command = Command::Render(render);
+		}
+
+		Ok(parsed)
+	}
+}
+
+// Shared with config.toml so neither can set it out of range
+pub fn check_volume(volume: f32) -> Result<f32> {
+	if !(0.0..=1.0).contains(&volume) {
+		return Err("Volume should be between 0.0 and 1.0".into());
+	}
+	Ok(volume)
+}
+
+fn parse_size(size: &str) -> Option<(u32, u32)> {
+	let mut parts = size.splitn(2, 'x');
+	let width = parts.next()?.parse().ok()?;
+	let height = parts.next()?.parse().ok()?;
+	if width == 0 || height == 0 {
+		return None;
+	}
+	Some((width, height))
+}
@@ -0,0 +1,39 @@
+// Everything that animates asks this for the time instead of using Instant::now() directly
+// so the offline renderer can step time forward one frame at a time
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+thread_local! {
+	// Start of the simulation and how far it's got
+	static SIMULATED: Cell<Option<(Instant, Duration)>> = const { Cell::new(None) };
+}
+
+pub fn now() -> Instant {
+	SIMULATED.with(|simulated| match simulated.get() {
+		Some((start, offset)) => start + offset,
+		None => Instant::now(),
+	})
+}
+
+pub fn elapsed(since: Instant) -> Duration {
+	let now = now();
+	if now > since {
+		now - since
+	} else {
+		Duration::new(0, 0)
+	}
+}
+
+// From here on, time only moves when advance is called
+pub fn simulate() {
+	SIMULATED.with(|simulated| simulated.set(Some((Instant::now(), Duration::new(0, 0)))));
+}
+
+pub fn advance(step: Duration) {
+	SIMULATED.with(|simulated| {
+		if let Some((start, offset)) = simulated.get() {
+			simulated.set(Some((start, offset + step)));
+		}
+	});
+}
@@ -0,0 +1,185 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use toml;
+
+use archive;
+use cli::{self, Args};
+use layouts::LayoutKind;
+use songs::BuildupMode;
+use Result;
+
+pub const DEFAULT_CONFIG: &str = "config.toml";
+pub const DEFAULT_FONT: &str = "respacks/PetMe64.ttf";
+
+// Everything is optional, anything missing falls back to the defaults
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+	// Names of packs in respacks/, or paths to them
+	pub respacks: Option<Vec<String>>,
+	pub song: Option<String>,
+	pub image: Option<String>,
+
+	pub width: Option<u32>,
+	pub height: Option<u32>,
+	pub fullscreen: Option<bool>,
+
+	pub full_auto: Option<bool>,
+	// 0.0 to 1.0
+	pub volume: Option<f32>,
+	pub muted: Option<bool>,
+	// How long the last song fades out for after changing, in milliseconds
+	pub fade: Option<u64>,
+	// Black out the screen until the fade is done
+	pub blackout_on_change: Option<bool>,
+	// always, once or never
+	pub buildup: Option<String>,
+	pub seed: Option<u64>,
+	pub font: Option<String>,
+	// basic, modern, minimal, weed or xmas
+	pub layout: Option<String>,
+
+	// Audio latency in milliseconds
+	pub latency: Option<i64>,
+
+	// Action name to key name, see keymap.rs
+	pub keys: Option<HashMap<String, String>>,
+}
+
+impl Config {
+	pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let mut config_string = String::new();
+		File::open(path)?.read_to_string(&mut config_string)?;
+		let config: Config = toml::from_str(&config_string)?;
+		if let Some(volume) = config.volume {
+			cli::check_volume(volume)?;
+		}
+		Ok(config)
+	}
+
+	// Anything given on the command line wins
+	pub fn merge(&mut self, args: &Args) {
+		if !args.respacks.is_empty() {
+			self.respacks = Some(args.respacks.clone());
+		}
+		if args.song.is_some() {
+			self.song = args.song.clone();
+		}
+		if args.image.is_some() {
+			self.image = args.image.clone();
+		}
+		if let Some((width, height)) = args.size {
+			self.width = Some(width);
+			self.height = Some(height);
+		}
+		if args.fullscreen.is_some() {
+			self.fullscreen = args.fullscreen;
+		}
+		if args.full_auto.is_some() {
+			self.full_auto = args.full_auto;
+		}
+		if args.volume.is_some() {
+			self.volume = args.volume;
+		}
+		if args.seed.is_some() {
+			self.seed = args.seed;
+		}
+		if args.font.is_some() {
+			self.font = args.font.clone();
+		}
+		if args.buildup.is_some() {
+			self.buildup = args.buildup.clone();
+		}
+	}
+
+	pub fn window_size(&self) -> (u32, u32) {
+		(self.width.unwrap_or(1280), self.height.unwrap_or(720))
+	}
+
+	pub fn layout(&self) -> LayoutKind {
+		match self.layout {
+			Some(ref name) => LayoutKind::from_str(name).unwrap_or_else(|| {
+				eprintln!("Warning: Unknown layout: {}", name);
+				LayoutKind::Basic
+			}),
+			None => LayoutKind::Basic,
+		}
+	}
+
+	pub fn buildup_mode(&self) -> BuildupMode {
+		match self.buildup {
+			Some(ref name) => BuildupMode::from_str(name).unwrap_or_else(|| {
+				eprintln!("Warning: Unknown buildup mode: {}", name);
+				BuildupMode::Always
+			}),
+			None => BuildupMode::Always,
+		}
+	}
+
+	pub fn font(&self) -> &str {
+		self.font.as_ref().map_or(DEFAULT_FONT, String::as_ref)
+	}
+
+	// Packs can be paths or just the name of something in respacks/
+	pub fn respack_paths(&self) -> Vec<PathBuf> {
+		if let Some(ref packs) = self.respacks {
+			packs
+				.iter()
+				.map(|pack| {
+					let path = PathBuf::from(pack);
+					if path.exists() {
+						path
+					} else {
+						archive::find_respack("respacks", pack)
+					}
+				})
+				.collect()
+		} else {
+			// Both zipped and unpacked packs
+			archive::list_respacks("respacks")
+		}
+	}
+
+	// Writes them into the config file, leaving everything else in it alone
+	pub fn save_volume<P: AsRef<Path>>(path: P, volume: f32, muted: bool) -> Result<()> {
+		let path = path.as_ref();
+		let mut config_string = String::new();
+		match File::open(path) {
+			Ok(mut file) => {
+				file.read_to_string(&mut config_string)?;
+			}
+			Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+			Err(err) => return Err(err.into()),
+		}
+
+		let config_string = set_value(&config_string, "volume", &volume.to_string());
+		let config_string = set_value(&config_string, "muted", &muted.to_string());
+		File::create(path)?.write_all(config_string.as_bytes())?;
+		Ok(())
+	}
+}
+
+// Replaces key = ... before the first [table], or adds it there if it isn't set yet
+fn set_value(config: &str, key: &str, value: &str) -> String {
+	let mut lines: Vec<String> = config.lines().map(String::from).collect();
+	let top_level = lines
+		.iter()
+		.position(|line| line.trim_start().starts_with('['))
+		.unwrap_or(lines.len());
+	let line = format!("{} = {}", key, value);
+
+	let existing = lines[..top_level].iter().position(|line| {
+		let line = line.trim_start();
+		line.starts_with(key) && line[key.len()..].trim_start().starts_with('=')
+	});
+	match existing {
+		Some(index) => lines[index] = line,
+		None => lines.insert(top_level, line),
+	}
+
+	let mut config = lines.join("\n");
+	config.push('\n');
+	config
+}
@@ -0,0 +1,142 @@
+// Who made the current image and song, and which packs they came from
+// Clicking a link copies it, since there's no telling what would open it
+
+use sdl2::pixels::Color as Colour;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use sdl2::ttf::Font;
+
+use glyphs::GlyphAtlas;
+use images::Image;
+use loader::PackInfo;
+use songs::Song;
+use ui::{centred_layout, scale_rect, TextUi};
+use Result;
+
+// Top left, in layout coordinates
+const CREDITS_X: i32 = 12;
+const CREDITS_Y: i32 = 12;
+const PADDING: i32 = 8;
+const LINE_HEIGHT: i32 = 14;
+
+const TEXT_COLOUR: Colour = Colour { r: 0x00, g: 0x00, b: 0x00, a: 0xFF };
+const LINK_COLOUR: Colour = Colour { r: 0x00, g: 0x00, b: 0xC0, a: 0xFF };
+
+pub struct CreditsPanel<'a, Target: 'a> {
+	// Links are shown as they are, everything else in capitals like the rest of the UI
+	lines: Vec<(TextUi, Option<String>)>,
+	// Of the widest line
+	width: u32,
+	atlas: GlyphAtlas<'a, Target>,
+}
+
+impl<'a, Target> CreditsPanel<'a, Target> {
+	pub fn new(font: &'a Font<'a, 'static>, texture_creator: &'a TextureCreator<Target>) -> Result<Self> {
+		Ok(CreditsPanel {
+			lines: Vec::new(),
+			width: 0,
+			atlas: GlyphAtlas::new(font, texture_creator)?,
+		})
+	}
+
+	// Called every frame it's shown, nothing changes unless the text does
+	pub fn update(
+		&mut self,
+		image: Option<(&Image, Option<&PackInfo>)>,
+		song: Option<(&Song, Option<&PackInfo>)>,
+	) -> Result<()> {
+		let mut lines = Vec::new();
+		if let Some((image, pack)) = image {
+			let name = image.fullname().unwrap_or_else(|| image.name());
+			lines.push((format!("IMAGE: {}", name).to_uppercase(), None));
+			for source in image.source().into_iter().chain(image.source_other()) {
+				lines.push((source.to_owned(), Some(source.to_owned())));
+			}
+			pack_lines(pack, &mut lines);
+		}
+		if let Some((song, pack)) = song {
+			lines.push((format!("SONG: {}", song.title).to_uppercase(), None));
+			if let Some(ref source) = song.source {
+				lines.push((source.clone(), Some(source.clone())));
+			}
+			pack_lines(pack, &mut lines);
+		}
+
+		while self.lines.len() < lines.len() {
+			let text = TextUi::create("", &mut self.atlas)?;
+			self.lines.push((text, None));
+		}
+		self.lines.truncate(lines.len());
+
+		self.width = 0;
+		for (i, (text, link)) in lines.into_iter().enumerate() {
+			let line = &mut self.lines[i];
+			line.0.set_text(text, &mut self.atlas)?;
+			line.0.set_pos(CREDITS_X + PADDING, CREDITS_Y + PADDING + i as i32 * LINE_HEIGHT);
+			line.0.set_colour(if link.is_some() { LINK_COLOUR } else { TEXT_COLOUR });
+			line.1 = link;
+			self.width = self.width.max(line.0.width());
+		}
+
+		Ok(())
+	}
+
+	fn rect(&self) -> Rect {
+		Rect::new(
+			CREDITS_X,
+			CREDITS_Y,
+			self.width + PADDING as u32 * 2,
+			(self.lines.len() as i32 * LINE_HEIGHT + PADDING * 2) as u32,
+		)
+	}
+
+	pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		if self.lines.is_empty() {
+			return Ok(());
+		}
+
+		let (width, height) = canvas.output_size()?;
+		let (scale, origin) = centred_layout(width, height);
+
+		canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xC0));
+		canvas.fill_rect(scale_rect(self.rect(), scale, origin))?;
+
+		for line in &self.lines {
+			line.0.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		}
+
+		Ok(())
+	}
+
+	// The link under a point on the output, if there is one
+	pub fn link_at(&self, x: i32, y: i32, (width, height): (u32, u32)) -> Option<&str> {
+		let (scale, origin) = centred_layout(width, height);
+		let x = ((x - origin.0) as f64 / scale) as i32;
+		let y = ((y - origin.1) as f64 / scale) as i32;
+		if !self.rect().contains_point((x, y)) {
+			return None;
+		}
+
+		let row = (y - CREDITS_Y - PADDING) / LINE_HEIGHT;
+		if row < 0 {
+			return None;
+		}
+		self.lines
+			.get(row as usize)
+			.and_then(|line| line.1.as_ref())
+			.map(String::as_ref)
+	}
+}
+
+fn pack_lines(pack: Option<&PackInfo>, lines: &mut Vec<(String, Option<String>)>) {
+	if let Some(pack) = pack {
+		let text = match pack.author() {
+			Some(author) => format!("PACK: {} BY {}", pack.name(), author),
+			None => format!("PACK: {}", pack.name()),
+		};
+		lines.push((text.to_uppercase(), None));
+		if let Some(link) = pack.link() {
+			lines.push((link.to_owned(), Some(link.to_owned())));
+		}
+	}
+}
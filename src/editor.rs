@@ -0,0 +1,664 @@
+// Edits the current song's rhythms while it plays, drawn as a grid over the waveform
+// Typed beats go in at the cursor and get applied as they come round, F2 saves them into the pack's songs.xml
+
+extern crate xml;
+
+use std::ffi::OsStr;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use sdl2::pixels::Color as Colour;
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use sdl2::ttf::Font;
+
+use editor::xml::reader::{self, ParserConfig};
+use editor::xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+use archive::{self, PackArchive};
+use beats;
+use glyphs::GlyphAtlas;
+use songs::{BeatIndex, Song, SongManager};
+use ui::{centred_layout, scale_rect, TextUi, UiLayout};
+use Result;
+
+// In layout coordinates
+const EDITOR_RECT: (i32, i32, u32, u32) = (40, 420, 1200, 280);
+const PADDING: i32 = 12;
+const LINE_HEIGHT: i32 = 14;
+// Status, keys and the last message
+const HEADER_LINES: i32 = 3;
+const COLUMNS: usize = 32;
+const ROWS: usize = 4;
+const PAGE: usize = COLUMNS * ROWS;
+const CELL_WIDTH: i32 = 36;
+const CELL_HEIGHT: i32 = 48;
+// Waveform bars under each beat
+const BARS: usize = 4;
+// Plenty for any sensible number of beats
+const WAVEFORM_SLICES: usize = 8192;
+
+const KEYS: &str = "TYPE BEATS  LEFT/RIGHT MOVE  UP/DOWN BUILDUP/LOOP  INSERT MODE  PGUP/PGDN RESOLUTION  \
+                    ENTER TAP  HOME PLAY FROM CURSOR  SPACE PAUSE  F2 SAVE  ESC CLOSE";
+
+const CURSOR_COLOUR: Colour = Colour { r: 0x00, g: 0x00, b: 0xC0, a: 0xFF };
+const PLAYING_COLOUR: Colour = Colour { r: 0xFF, g: 0x00, b: 0x00, a: 0x40 };
+const WAVEFORM_COLOUR: Colour = Colour { r: 0x00, g: 0x00, b: 0x00, a: 0x20 };
+const GRID_COLOUR: Colour = Colour { r: 0x00, g: 0x00, b: 0x00, a: 0x18 };
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Section {
+	Buildup,
+	Loop,
+}
+
+pub struct Editor<'a, Target: 'a> {
+	// Which song it is, in case it goes away
+	name: String,
+	pack: PathBuf,
+	title: String,
+	has_buildup: bool,
+
+	rhythm: Vec<char>,
+	buildup_rhythm: Vec<char>,
+	// As they were when it was opened or last saved, for putting back on close
+	saved_rhythm: Vec<char>,
+	saved_buildup_rhythm: Vec<char>,
+	loop_waveform: Vec<f32>,
+	buildup_waveform: Vec<f32>,
+
+	section: Section,
+	// Can be one past the end when inserting
+	cursor: usize,
+	insert: bool,
+	// What tapping puts down, the last beat typed
+	tap_beat: char,
+	// Changed since it was last saved
+	unsaved: bool,
+	message: String,
+
+	status_text: TextUi,
+	keys_text: TextUi,
+	message_text: TextUi,
+	cells: Vec<TextUi>,
+	atlas: GlyphAtlas<'a, Target>,
+}
+
+impl<'a, Target> Editor<'a, Target> {
+	// Decodes the song for the waveform, so it takes a moment to open
+	pub fn new(
+		song_manager: &SongManager,
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Result<Self> {
+		let song = song_manager.current_song().ok_or("No song to edit")?;
+		let loop_waveform = song.waveform(false, WAVEFORM_SLICES)?;
+		let buildup_waveform = if song.has_buildup() {
+			song.waveform(true, WAVEFORM_SLICES)?
+		} else {
+			Vec::new()
+		};
+
+		let mut atlas = GlyphAtlas::new(font, texture_creator)?;
+		let (x, y) = (EDITOR_RECT.0 + PADDING, EDITOR_RECT.1 + PADDING);
+		let mut status_text = TextUi::create("", &mut atlas)?;
+		status_text.set_pos(x, y);
+		let mut keys_text = TextUi::create(KEYS, &mut atlas)?;
+		keys_text.set_pos(x, y + LINE_HEIGHT);
+		let mut message_text = TextUi::create("", &mut atlas)?;
+		message_text.set_pos(x, y + LINE_HEIGHT * 2);
+
+		let mut cells = Vec::with_capacity(PAGE);
+		for _ in 0..PAGE {
+			cells.push(TextUi::create("", &mut atlas)?);
+		}
+
+		// Starts wherever it's playing
+		let (section, cursor) = match song_manager.current_beat() {
+			Some(BeatIndex::Buildup(beat)) if song.has_buildup() => {
+				(Section::Buildup, beat.min(song.buildup_rhythm.len() - 1))
+			}
+			Some(BeatIndex::Loop(beat)) => (Section::Loop, beat % song.rhythm.len()),
+			_ => (Section::Loop, 0),
+		};
+
+		Ok(Editor {
+			name: song.name().to_owned(),
+			pack: song.pack().to_owned(),
+			title: song.title.to_uppercase(),
+			has_buildup: song.has_buildup(),
+
+			rhythm: song.rhythm.clone(),
+			buildup_rhythm: song.buildup_rhythm.clone(),
+			saved_rhythm: song.rhythm.clone(),
+			saved_buildup_rhythm: song.buildup_rhythm.clone(),
+			loop_waveform,
+			buildup_waveform,
+
+			section,
+			cursor,
+			insert: false,
+			tap_beat: 'x',
+			unsaved: false,
+			message: String::new(),
+
+			status_text,
+			keys_text,
+			message_text,
+			cells,
+			atlas,
+		})
+	}
+
+	// Whether it's still the song that's playing
+	pub fn is_editing(&self, song: Option<&Song>) -> bool {
+		song.is_some_and(|song| song.name() == self.name && song.pack() == self.pack)
+	}
+
+	pub fn is_unsaved(&self) -> bool {
+		self.unsaved
+	}
+
+	// Anything that wasn't saved gets undone
+	pub fn close<S: UiLayout>(self, song_manager: &mut SongManager, ui: &mut S) {
+		if self.unsaved {
+			song_manager.set_rhythm(&self.pack, &self.name, self.saved_rhythm, self.saved_buildup_rhythm, ui);
+		}
+	}
+
+	pub fn title(&self) -> &str {
+		&self.title
+	}
+
+	fn beats(&self) -> &Vec<char> {
+		match self.section {
+			Section::Buildup => &self.buildup_rhythm,
+			Section::Loop => &self.rhythm,
+		}
+	}
+
+	fn beats_mut(&mut self) -> &mut Vec<char> {
+		match self.section {
+			Section::Buildup => &mut self.buildup_rhythm,
+			Section::Loop => &mut self.rhythm,
+		}
+	}
+
+	// Anything that isn't a beat gets ignored
+	pub fn type_text<S: UiLayout>(&mut self, text: &str, song_manager: &mut SongManager, ui: &mut S) {
+		let mut changed = false;
+		for beat in text.chars().filter(|&beat| beats::effect(beat).is_some()) {
+			let (cursor, insert) = (self.cursor, self.insert);
+			if insert {
+				self.beats_mut().insert(cursor, beat);
+			} else {
+				self.beats_mut()[cursor] = beat;
+			}
+			self.tap_beat = beat;
+			self.move_cursor(1);
+			changed = true;
+		}
+		if changed {
+			self.apply(song_manager, ui);
+		}
+	}
+
+	pub fn move_cursor(&mut self, change: i32) {
+		let len = self.beats().len();
+		let last = if self.insert { len } else { len - 1 };
+		self.cursor = (self.cursor as i32 + change).max(0).min(last as i32) as usize;
+	}
+
+	pub fn toggle_insert(&mut self) {
+		self.insert = !self.insert;
+		self.move_cursor(0);
+	}
+
+	pub fn show_buildup(&mut self, buildup: bool) {
+		let section = if buildup && self.has_buildup {
+			Section::Buildup
+		} else {
+			Section::Loop
+		};
+		if section != self.section {
+			self.section = section;
+			self.cursor = 0;
+		}
+	}
+
+	// Neither rhythm can be left empty, the beat length comes from how many there are
+	pub fn backspace<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		if self.cursor == 0 || self.beats().len() == 1 {
+			return;
+		}
+		self.cursor -= 1;
+		let cursor = self.cursor;
+		self.beats_mut().remove(cursor);
+		self.apply(song_manager, ui);
+	}
+
+	pub fn delete<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		let cursor = self.cursor;
+		if cursor >= self.beats().len() || self.beats().len() == 1 {
+			return;
+		}
+		self.beats_mut().remove(cursor);
+		self.move_cursor(0);
+		self.apply(song_manager, ui);
+	}
+
+	// A gap after every beat, so everything stays where it was with twice the detail
+	pub fn double_resolution<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		let doubled = self.beats().iter().flat_map(|&beat| vec![beat, '.']).collect();
+		*self.beats_mut() = doubled;
+		self.cursor *= 2;
+		self.message = format!("{} beats", self.beats().len());
+		self.apply(song_manager, ui);
+	}
+
+	// Each pair becomes one beat, the first unless it's a gap
+	pub fn halve_resolution<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		let len = self.beats().len();
+		if len % 2 == 1 {
+			self.message = format!("Can't halve {} beats", len);
+			return;
+		}
+
+		let pairs: Vec<(char, char)> = self.beats().chunks(2).map(|pair| (pair[0], pair[1])).collect();
+		let lost = pairs
+			.iter()
+			.filter(|&&(first, second)| !beats::is_empty(first) && !beats::is_empty(second))
+			.count();
+		*self.beats_mut() = pairs
+			.into_iter()
+			.map(|(first, second)| if beats::is_empty(first) { second } else { first })
+			.collect();
+		self.cursor /= 2;
+		self.message = match lost {
+			0 => format!("{} beats", len / 2),
+			lost => format!("{} beats, lost {}", len / 2, lost),
+		};
+		self.apply(song_manager, ui);
+	}
+
+	// Puts the last typed beat down wherever it's playing, and moves there
+	pub fn tap<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		let (section, beat) = match song_manager.nearest_beat() {
+			Some(BeatIndex::Buildup(beat)) if self.has_buildup => (Section::Buildup, beat),
+			Some(BeatIndex::Loop(beat)) => (Section::Loop, beat),
+			_ => return,
+		};
+		self.section = section;
+		self.cursor = beat.min(self.beats().len() - 1);
+
+		let (cursor, beat) = (self.cursor, self.tap_beat);
+		self.beats_mut()[cursor] = beat;
+		self.apply(song_manager, ui);
+	}
+
+	pub fn play_from_cursor<S: UiLayout>(&self, song_manager: &mut SongManager, ui: &mut S) {
+		let cursor = self.cursor.min(self.beats().len() - 1);
+		let beat = match self.section {
+			Section::Buildup => BeatIndex::Buildup(cursor),
+			Section::Loop => BeatIndex::Loop(cursor),
+		};
+		song_manager.seek_beat(beat, ui);
+	}
+
+	// The song plays whatever's in the editor straight away
+	fn apply<S: UiLayout>(&mut self, song_manager: &mut SongManager, ui: &mut S) {
+		song_manager.set_rhythm(&self.pack, &self.name, self.rhythm.clone(), self.buildup_rhythm.clone(), ui);
+		self.unsaved = true;
+	}
+
+	pub fn save(&mut self) {
+		let buildup_rhythm = if self.has_buildup {
+			Some(&self.buildup_rhythm[..])
+		} else {
+			None
+		};
+		match save_rhythm(&self.pack, &self.name, &self.rhythm, buildup_rhythm) {
+			Ok(file) => {
+				self.message = format!("Saved to {}", file.display());
+				self.saved_rhythm = self.rhythm.clone();
+				self.saved_buildup_rhythm = self.buildup_rhythm.clone();
+				self.unsaved = false;
+			}
+			Err(err) => self.message = format!("Could not save: {}", err),
+		}
+	}
+
+	fn cell_pos(index: usize) -> (i32, i32) {
+		let x = EDITOR_RECT.0 + PADDING;
+		let y = EDITOR_RECT.1 + PADDING + HEADER_LINES * LINE_HEIGHT + 4;
+		(
+			x + (index % COLUMNS) as i32 * CELL_WIDTH,
+			y + (index / COLUMNS) as i32 * CELL_HEIGHT,
+		)
+	}
+
+	// Playing is the beat that was last applied
+	pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>, playing: Option<BeatIndex>) -> Result<()> {
+		let len = self.beats().len();
+		let section_name = match self.section {
+			Section::Buildup => "BUILDUP",
+			Section::Loop => "LOOP",
+		};
+		let status = format!(
+			"EDITING {} - {} {}/{} - {} - TAP {}{}",
+			self.title,
+			section_name,
+			self.cursor + 1,
+			len,
+			if self.insert { "INSERT" } else { "OVERWRITE" },
+			self.tap_beat,
+			if self.unsaved { " - UNSAVED" } else { "" }
+		);
+		self.status_text.set_text(status, &mut self.atlas)?;
+		self.message_text.set_text(self.message.to_uppercase(), &mut self.atlas)?;
+
+		// The page the cursor's on
+		let page_start = self.cursor / PAGE * PAGE;
+		let shown = len.saturating_sub(page_start).min(PAGE);
+		let (beats, waveform) = match self.section {
+			Section::Buildup => (&self.buildup_rhythm, &self.buildup_waveform),
+			Section::Loop => (&self.rhythm, &self.loop_waveform),
+		};
+		for (i, cell) in self.cells.iter_mut().enumerate() {
+			let text = beats.get(page_start + i).map(char::to_string).unwrap_or_default();
+			cell.set_text(text, &mut self.atlas)?;
+			let (x, y) = Self::cell_pos(i);
+			cell.centre(x, y, CELL_WIDTH as u32, CELL_HEIGHT as u32);
+		}
+
+		let (width, height) = canvas.output_size()?;
+		let (scale, origin) = centred_layout(width, height);
+		let cell_rect = |i: usize| {
+			let (x, y) = Self::cell_pos(i);
+			scale_rect(Rect::new(x, y, CELL_WIDTH as u32, CELL_HEIGHT as u32), scale, origin)
+		};
+
+		let (x, y, w, h) = EDITOR_RECT;
+		canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xE0));
+		canvas.fill_rect(scale_rect(Rect::new(x, y, w, h), scale, origin))?;
+
+		for i in 0..shown {
+			canvas.set_draw_color(GRID_COLOUR);
+			canvas.draw_rect(cell_rect(i))?;
+
+			if waveform.is_empty() {
+				continue;
+			}
+			canvas.set_draw_color(WAVEFORM_COLOUR);
+			let (cell_x, cell_y) = Self::cell_pos(i);
+			let bar_width = CELL_WIDTH / BARS as i32;
+			for bar in 0..BARS {
+				// At least one slice each, even with more bars than slices
+				let piece = (page_start + i) * BARS + bar;
+				let start = (piece * waveform.len() / (len * BARS)).min(waveform.len() - 1);
+				let end = ((piece + 1) * waveform.len() / (len * BARS)).max(start + 1).min(waveform.len());
+				let peak = waveform[start..end].iter().cloned().fold(0.0, f32::max);
+
+				let bar_height = (peak * CELL_HEIGHT as f32) as i32;
+				let rect = Rect::new(
+					cell_x + bar as i32 * bar_width,
+					cell_y + (CELL_HEIGHT - bar_height) / 2,
+					(bar_width - 1) as u32,
+					bar_height.max(1) as u32,
+				);
+				canvas.fill_rect(scale_rect(rect, scale, origin))?;
+			}
+		}
+
+		let playing = match (playing, self.section) {
+			(Some(BeatIndex::Buildup(beat)), Section::Buildup) | (Some(BeatIndex::Loop(beat)), Section::Loop) => Some(beat),
+			_ => None,
+		};
+		if let Some(beat) = playing {
+			if beat >= page_start && beat < page_start + shown {
+				canvas.set_draw_color(PLAYING_COLOUR);
+				canvas.fill_rect(cell_rect(beat - page_start))?;
+			}
+		}
+
+		// A line before the beat when inserting, a box around it when overwriting
+		canvas.set_draw_color(CURSOR_COLOUR);
+		let cursor = cell_rect(self.cursor - page_start);
+		if self.insert {
+			let line = Rect::new(cursor.x(), cursor.y(), (2.0 * scale).max(1.0) as u32, cursor.height());
+			canvas.fill_rect(line)?;
+		} else {
+			canvas.draw_rect(cursor)?;
+		}
+
+		self.status_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.keys_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.message_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		for cell in &self.cells {
+			cell.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		}
+
+		Ok(())
+	}
+}
+
+// Puts the rhythms into whichever xml file has the song, returns the file it went into
+fn save_rhythm(pack: &Path, name: &str, rhythm: &[char], buildup_rhythm: Option<&[char]>) -> Result<PathBuf> {
+	let rhythm: String = rhythm.iter().collect();
+	let buildup_rhythm: Option<String> = buildup_rhythm.map(|beats| beats.iter().collect());
+
+	let mut found = None;
+	{
+		let mut archive = PackArchive::open(pack)?;
+		for i in 0..archive.num_files() {
+			let mut file = archive.by_index(i)?;
+			let extension = file.path.extension().and_then(OsStr::to_str).map(str::to_lowercase);
+			if extension.as_ref().map(String::as_ref) != Some("xml") {
+				continue;
+			}
+
+			let mut xml = Vec::new();
+			if file.reader.read_to_end(&mut xml).is_err() {
+				continue;
+			}
+			// Anything that doesn't parse can't be the one with the song in it
+			if let Ok(Some(xml)) = replace_rhythms(&xml, name, &rhythm, buildup_rhythm.as_ref().map(String::as_ref)) {
+				found = Some((file.path.clone(), xml));
+				break;
+			}
+		}
+	}
+
+	let (file, xml) = found.ok_or_else(|| format!("No xml in the pack has {}", name))?;
+	archive::replace_file(pack, &file, &xml)?;
+	Ok(file)
+}
+
+// Copies the xml through with the song's rhythms swapped out, None if the song isn't in it
+// Missing rhythms get added at the end of the song, lined up with the rest
+fn replace_rhythms(xml: &[u8], name: &str, rhythm: &str, buildup_rhythm: Option<&str>) -> Result<Option<Vec<u8>>> {
+	let mut missing = vec![("rhythm", rhythm)];
+	if let Some(buildup_rhythm) = buildup_rhythm {
+		missing.push(("buildupRhythm", buildup_rhythm));
+	}
+
+	let reader = ParserConfig::new()
+		.trim_whitespace(false)
+		.ignore_comments(false)
+		.create_reader(xml);
+	let mut output = Vec::new();
+	let mut found = false;
+	{
+		let mut writer = EmitterConfig::new()
+			.normalize_empty_elements(false)
+			.autopad_comments(false)
+			.create_writer(&mut output);
+
+		let mut depth = 0;
+		// How deep the song's element is, while inside it
+		let mut song_depth = None;
+		// Inside a rhythm that's being replaced
+		let mut skipping = false;
+		// Whitespace between the song's fields, the last bit is held back in case something gets added before it
+		let mut indent: Option<String> = None;
+		let mut pending: Option<String> = None;
+
+		for event in reader {
+			let event = event?;
+			if skipping {
+				if let reader::XmlEvent::EndElement { .. } = event {
+					depth -= 1;
+					if Some(depth) == song_depth {
+						skipping = false;
+						writer.write(event.as_writer_event().unwrap())?;
+					}
+				} else if let reader::XmlEvent::StartElement { .. } = event {
+					depth += 1;
+				}
+				continue;
+			}
+
+			if song_depth == Some(depth) {
+				if let reader::XmlEvent::Whitespace(ref text) = event {
+					if indent.is_none() {
+						indent = Some(text.clone());
+					}
+					if let Some(pending) = pending.replace(text.clone()) {
+						writer.write(XmlEvent::characters(&pending))?;
+					}
+					continue;
+				}
+			}
+
+			match event {
+				reader::XmlEvent::StartElement { name: ref element, ref attributes, .. } => {
+					if song_depth == Some(depth) {
+						if let Some(position) = missing.iter().position(|&(field, _)| field == element.local_name) {
+							let (_, value) = missing.remove(position);
+							flush(&mut writer, &mut pending)?;
+							writer.write(event.as_writer_event().unwrap())?;
+							writer.write(XmlEvent::characters(value))?;
+							depth += 1;
+							skipping = true;
+							continue;
+						}
+					}
+					let is_song = element.local_name == "song"
+						&& attributes.iter().any(|attribute| attribute.name.local_name == "name" && attribute.value == name);
+					if !found && is_song {
+						found = true;
+						song_depth = Some(depth + 1);
+					}
+					depth += 1;
+				}
+				reader::XmlEvent::EndElement { .. } => {
+					if song_depth == Some(depth) {
+						for (field, value) in missing.drain(..) {
+							if let Some(ref indent) = indent {
+								writer.write(XmlEvent::characters(indent))?;
+							}
+							writer.write(XmlEvent::start_element(field))?;
+							writer.write(XmlEvent::characters(value))?;
+							writer.write(XmlEvent::end_element())?;
+						}
+						song_depth = None;
+					}
+					depth -= 1;
+				}
+				_ => {}
+			}
+
+			flush(&mut writer, &mut pending)?;
+			if let Some(event) = event.as_writer_event() {
+				writer.write(event)?;
+			}
+		}
+	}
+
+	Ok(if found { Some(output) } else { None })
+}
+
+fn flush<W: Write>(writer: &mut EventWriter<W>, pending: &mut Option<String>) -> Result<()> {
+	if let Some(text) = pending.take() {
+		writer.write(XmlEvent::characters(&text))?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::replace_rhythms;
+
+	fn replace(xml: &str, name: &str, buildup_rhythm: Option<&str>) -> Option<String> {
+		replace_rhythms(xml.as_bytes(), name, "x.o.", buildup_rhythm)
+			.unwrap()
+			.map(|xml| String::from_utf8(xml).unwrap())
+	}
+
+	const DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>";
+
+	#[test]
+	fn replaces_rhythm() {
+		let xml = "<songs>\n\t<song name=\"a\">\n\t\t<title>A</title>\n\t\t<rhythm>....</rhythm>\n\t</song>\n</songs>";
+		let expected = "<songs>\n\t<song name=\"a\">\n\t\t<title>A</title>\n\t\t<rhythm>x.o.</rhythm>\n\t</song>\n</songs>";
+		assert_eq!(replace(xml, "a", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn adds_missing_rhythms_lined_up() {
+		let xml = "<songs>\n\t<song name=\"a\">\n\t\t<title>A</title>\n\t</song>\n</songs>";
+		let expected = "<songs>\n\t<song name=\"a\">\n\t\t<title>A</title>\n\t\t<rhythm>x.o.</rhythm>\
+		                \n\t\t<buildupRhythm>-</buildupRhythm>\n\t</song>\n</songs>";
+		assert_eq!(replace(xml, "a", Some("-")), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn self_closing_rhythm() {
+		let xml = "<songs><song name=\"a\"><rhythm/></song></songs>";
+		let expected = "<songs><song name=\"a\"><rhythm>x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn rhythm_with_attributes() {
+		let xml = "<songs><song name=\"a\"><rhythm note=\"old\">....</rhythm></song></songs>";
+		let expected = "<songs><song name=\"a\"><rhythm note=\"old\">x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn single_quoted_name() {
+		let xml = "<songs><song name='a'><rhythm>....</rhythm></song></songs>";
+		let expected = "<songs><song name=\"a\"><rhythm>x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn songs_isnt_a_song() {
+		// <songs> starts the same way as <song, but it's the song inside that has the name
+		let xml = "<songs name=\"a\"><song name=\"b\"><rhythm>....</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a", None), None);
+	}
+
+	#[test]
+	fn escaped_name() {
+		let xml = "<songs><song name=\"a &amp; b\"><rhythm>....</rhythm></song></songs>";
+		let expected = "<songs><song name=\"a &amp; b\"><rhythm>x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a & b", None), Some(format!("{}{}", DECLARATION, expected)));
+		assert_eq!(replace(xml, "a &amp; b", None), None);
+	}
+
+	#[test]
+	fn leaves_other_songs_alone() {
+		let xml = "<songs><song name=\"a\"><rhythm>....</rhythm></song><song name=\"b\"><rhythm>....</rhythm></song></songs>";
+		let expected = "<songs><song name=\"a\"><rhythm>....</rhythm></song><song name=\"b\"><rhythm>x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "b", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+
+	#[test]
+	fn keeps_comments() {
+		let xml = "<songs><!-- a comment --><song name=\"a\"><rhythm>....</rhythm></song></songs>";
+		let expected = "<songs><!-- a comment --><song name=\"a\"><rhythm>x.o.</rhythm></song></songs>";
+		assert_eq!(replace(xml, "a", None), Some(format!("{}{}", DECLARATION, expected)));
+	}
+}
@@ -0,0 +1,144 @@
+// Every character the UI has needed so far, rendered once into one texture
+// Text is drawn by copying glyphs out of it, so changing the text every frame is cheap
+
+use std::collections::HashMap;
+
+use sdl2::pixels::{Color as Colour, PixelFormatEnum};
+use sdl2::render::{BlendMode, Texture, TextureCreator};
+use sdl2::rect::Rect;
+use sdl2::surface::Surface;
+use sdl2::ttf::Font;
+
+use beats::BEATS;
+use Result;
+
+const ATLAS_WIDTH: u32 = 512;
+
+pub struct GlyphAtlas<'a, Target: 'a> {
+	font: &'a Font<'a, 'static>,
+	texture_creator: &'a TextureCreator<Target>,
+
+	// Glyphs get added here, then the whole thing is uploaded again
+	surface: Surface<'static>,
+	texture: Texture,
+	glyphs: HashMap<char, Rect>,
+
+	// Where the next glyph goes
+	next_x: i32,
+	next_y: i32,
+	line_height: u32,
+}
+
+impl<'a, Target> GlyphAtlas<'a, Target> {
+	pub fn new(
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Result<Self> {
+		let line_height = font.height().max(1) as u32;
+		let surface = Surface::new(ATLAS_WIDTH, line_height * 4, PixelFormatEnum::ARGB8888)?;
+		let texture = texture_creator.create_texture_from_surface(&surface)?;
+
+		let mut atlas = GlyphAtlas {
+			font,
+			texture_creator,
+
+			surface,
+			texture,
+			glyphs: HashMap::new(),
+
+			next_x: 0,
+			next_y: 0,
+			line_height,
+		};
+
+		// Printable ascii and the beat characters, which covers almost everything
+		let common: String = (0x20u8..0x7F)
+			.map(|ch| ch as char)
+			.chain(BEATS.iter().map(|&(ch, _, _)| ch))
+			.collect();
+		atlas.add_glyphs(&common)?;
+
+		Ok(atlas)
+	}
+
+	pub fn line_height(&self) -> u32 {
+		self.line_height
+	}
+
+	// Adds anything in text that isn't there yet
+	pub fn add_glyphs(&mut self, text: &str) -> Result<()> {
+		let mut added = false;
+		for ch in text.chars() {
+			if self.glyphs.contains_key(&ch) {
+				continue;
+			}
+			// The font doesn't have it, so it'll get drawn as a '?'
+			if self.font.find_glyph(ch).is_none() {
+				continue;
+			}
+			self.add_glyph(ch)?;
+			added = true;
+		}
+
+		if added {
+			self.texture = self.texture_creator.create_texture_from_surface(&self.surface)?;
+			self.texture.set_blend_mode(BlendMode::Blend);
+		}
+		Ok(())
+	}
+
+	fn add_glyph(&mut self, ch: char) -> Result<()> {
+		let mut glyph = self.font.render_char(ch).blended(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xFF))?;
+		let (width, height) = (glyph.width(), glyph.height());
+
+		if self.next_x + width as i32 > ATLAS_WIDTH as i32 {
+			self.next_x = 0;
+			self.next_y += self.line_height as i32;
+		}
+		if self.next_y + height as i32 > self.surface.height() as i32 {
+			self.grow()?;
+		}
+
+		// Copy the alpha straight over instead of blending onto nothing
+		let rect = Rect::new(self.next_x, self.next_y, width.max(1), height.max(1));
+		glyph.set_blend_mode(BlendMode::None)?;
+		glyph.blit(None, &mut self.surface, rect)?;
+
+		self.glyphs.insert(ch, rect);
+		self.next_x += width as i32;
+
+		Ok(())
+	}
+
+	// Double the height, keeping what's there
+	fn grow(&mut self) -> Result<()> {
+		let height = self.surface.height() * 2;
+		let mut surface = Surface::new(ATLAS_WIDTH, height, PixelFormatEnum::ARGB8888)?;
+		self.surface.set_blend_mode(BlendMode::None)?;
+		self.surface.blit(None, &mut surface, None)?;
+		self.surface = surface;
+		Ok(())
+	}
+
+	pub fn glyph(&self, ch: char) -> Option<Rect> {
+		self.glyphs.get(&ch).or_else(|| self.glyphs.get(&'?')).cloned()
+	}
+
+	// Width of text in pixels, at the font's size
+	pub fn measure(&self, text: &str) -> u32 {
+		text.chars()
+			.filter_map(|ch| self.glyph(ch))
+			.map(|rect| rect.width())
+			.sum()
+	}
+
+	// The glyphs are white, so this is the colour they come out as
+	pub fn set_colour(&mut self, colour: Colour) {
+		self.texture.set_color_mod(colour.r, colour.g, colour.b);
+		self.texture.set_alpha_mod(colour.a);
+	}
+
+	pub fn texture(&self) -> &Texture {
+		&self.texture
+	}
+}
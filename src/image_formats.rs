@@ -0,0 +1,168 @@
+// Decodes the images in a respack
+// SDL_image does the still formats, but only ever gives the first frame of a gif
+// so those go through the gif crate and come out as an animation
+
+extern crate gif;
+
+use std::time::Duration;
+
+use sdl2::rwops::RWops;
+use sdl2::image::ImageRWops;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::surface::Surface as STSurface;
+
+use image_formats::gif::{ColorOutput, DisposalMethod, SetParameter};
+
+use surface::Surface;
+use Result;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+	Png,
+	Jpeg,
+	Gif,
+	WebP,
+}
+
+impl ImageFormat {
+	pub fn from_extension(extension: &str) -> Option<Self> {
+		match extension.to_lowercase().as_ref() {
+			"png" => Some(ImageFormat::Png),
+			"jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+			"gif" => Some(ImageFormat::Gif),
+			"webp" => Some(ImageFormat::WebP),
+			_ => None,
+		}
+	}
+
+	pub fn sniff(data: &[u8]) -> Option<Self> {
+		if data.starts_with(b"\x89PNG") {
+			Some(ImageFormat::Png)
+		} else if data.starts_with(b"\xFF\xD8\xFF") {
+			Some(ImageFormat::Jpeg)
+		} else if data.starts_with(b"GIF8") {
+			Some(ImageFormat::Gif)
+		} else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+			Some(ImageFormat::WebP)
+		} else {
+			None
+		}
+	}
+}
+
+pub struct DecodedImage {
+	pub frames: Vec<Surface>,
+	// Empty for still images
+	pub frame_durations: Vec<Duration>,
+}
+
+pub fn decode(data: &[u8], extension: Option<&str>) -> Result<DecodedImage> {
+	let format = ImageFormat::sniff(data)
+		.or_else(|| extension.and_then(ImageFormat::from_extension))
+		.ok_or("Unknown image format")?;
+
+	if format == ImageFormat::Gif {
+		return decode_gif(data);
+	}
+
+	let rwops = RWops::from_bytes(data)?;
+	let surface = match format {
+		ImageFormat::Png => rwops.load_png()?,
+		ImageFormat::Jpeg => rwops.load_jpg()?,
+		ImageFormat::WebP => rwops.load_webp()?,
+		ImageFormat::Gif => unreachable!(),
+	};
+
+	Ok(DecodedImage {
+		frames: vec![Surface::from_surface(surface)?],
+		frame_durations: Vec::new(),
+	})
+}
+
+// Gif frames only cover part of the image and get drawn over the previous one,
+// so each frame is put together on a full sized canvas before being turned into a surface
+fn decode_gif(data: &[u8]) -> Result<DecodedImage> {
+	let mut decoder = gif::Decoder::new(data);
+	decoder.set(ColorOutput::RGBA);
+	let mut reader = decoder.read_info()?;
+
+	let width = reader.width() as usize;
+	let height = reader.height() as usize;
+	let mut canvas = vec![0u8; width * height * 4];
+
+	let mut frames = Vec::new();
+	let mut frame_durations = Vec::new();
+
+	while let Some(frame) = reader.read_next_frame()? {
+		let previous = if frame.dispose == DisposalMethod::Previous {
+			Some(canvas.clone())
+		} else {
+			None
+		};
+
+		let (left, top) = (frame.left as usize, frame.top as usize);
+		let (frame_width, frame_height) = (frame.width as usize, frame.height as usize);
+		for y in 0..frame_height {
+			if top + y >= height {
+				break;
+			}
+			for x in 0..frame_width {
+				if left + x >= width {
+					break;
+				}
+				let src = (y * frame_width + x) * 4;
+				let dst = ((top + y) * width + left + x) * 4;
+				// Transparent pixels leave what was there before
+				if frame.buffer[src + 3] != 0 {
+					canvas[dst..dst + 4].copy_from_slice(&frame.buffer[src..src + 4]);
+				}
+			}
+		}
+
+		let mut pixels = canvas.clone();
+		let surface = STSurface::from_data(
+			&mut pixels,
+			width as u32,
+			height as u32,
+			width as u32 * 4,
+			PixelFormatEnum::ABGR8888,
+		)?;
+		frames.push(Surface::from_surface(surface)?);
+
+		// Delay is in hundredths of a second, and browsers treat 0 as 100ms
+		let delay = if frame.delay == 0 { 10 } else { frame.delay as u64 };
+		frame_durations.push(Duration::from_millis(delay * 10));
+
+		match frame.dispose {
+			DisposalMethod::Background if left < width => {
+				for y in top..(top + frame_height).min(height) {
+					let start = (y * width + left) * 4;
+					let end = (y * width + (left + frame_width).min(width)) * 4;
+					for byte in canvas[start..end].iter_mut() {
+						*byte = 0;
+					}
+				}
+			}
+			DisposalMethod::Previous => {
+				if let Some(previous) = previous {
+					canvas = previous;
+				}
+			}
+			_ => {}
+		}
+	}
+
+	if frames.is_empty() {
+		return Err("Gif has no frames".into());
+	}
+
+	// A single frame gif is just a still image
+	if frames.len() == 1 {
+		frame_durations.clear();
+	}
+
+	Ok(DecodedImage {
+		frames,
+		frame_durations,
+	})
+}
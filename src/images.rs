@@ -1,23 +1,40 @@
-use std::time::Instant;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
-use rand::{thread_rng as rng, Rng};
+use rand::Rng;
 
 use sdl2::rect::Rect;
-use sdl2::render::{Texture, TextureCreator, WindowCanvas as Canvas};
+use sdl2::render::{Canvas, RenderTarget, Texture, TextureCreator, TextureQuery};
 
 use loader::ImageLoader;
+use beats::Slice;
 use ui::UiLayout;
 use Result;
 
 use duration_to_secs;
+use clock;
+use Random;
+
+// Milliseconds, for animations that don't say
+const DEFAULT_FRAME_DURATION: u64 = 100;
+// Fraction of the screen height, 40px at 720p
+const BLUR_DISTANCE: f64 = 40.0 / 720.0;
 
 pub struct ImageManager<'a, Target: 'a> {
 	images: Vec<Image>,
 	curr_index: Option<usize>,
 
 	full_auto: bool,
+	// Beats can't change the image
+	locked: bool,
 
 	blur: Blur,
+	blur_enabled: bool,
+	slices: Option<Slices>,
+
+	// For animations
+	anim_start: Instant,
+	beat: f64,
 
 	texture_creator: &'a TextureCreator<Target>,
 }
@@ -29,113 +46,385 @@ impl<'a, Target> ImageManager<'a, Target> {
 			curr_index: None,
 
 			full_auto: true,
+			locked: false,
 
 			blur: Blur::new(7),
+			blur_enabled: true,
+			slices: None,
+
+			anim_start: clock::now(),
+			beat: 0.0,
 
 			texture_creator,
 		}
 	}
 
-	pub fn extend(&mut self, pack: Vec<ImageLoader>) {
+	pub fn extend(&mut self, pack: &Path, images: Vec<ImageLoader>) {
 		let texture_creator = self.texture_creator;
-		self.images.extend(pack.into_iter().map(|image_loader| {
-			let texture = texture_creator
-				.create_texture_from_surface(&image_loader.data)
-				.unwrap();
-
-			Image::from_loader(image_loader, texture)
+		self.images.extend(images.into_iter().map(|image_loader| {
+			let textures = image_loader
+				.frames
+				.iter()
+				.map(|frame| texture_creator.create_texture_from_surface(frame).unwrap())
+				.collect();
+
+			Image::from_loader(pack, image_loader, textures)
 		}));
 	}
 
-	// Ok this isn't actually "random image" but it's not being used anywhere else so it stays like this for now
-	pub fn random_image<S: UiLayout>(&mut self, ui: &mut S) {
-		if self.full_auto {
-			if !self.images.is_empty() {
-				let idx = rng().gen_range(0, self.images.len());
-				ui.update_image(&self.images[idx].name);
+	// If the current image goes, another one gets picked even if it's locked
+	pub fn remove_pack<S: UiLayout>(&mut self, pack: &Path, rng: &mut Random, ui: &mut S) {
+		let images = &self.images;
+		let curr_index = self.curr_index
+			.filter(|&index| images[index].pack != pack)
+			.map(|index| images[..index].iter().filter(|image| image.pack != pack).count());
+
+		let (removed, kept): (Vec<_>, Vec<_>) = self.images.drain(..).partition(|image| image.pack == pack);
+		self.images = kept;
+		self.curr_index = curr_index;
+
+		// With unsafe_textures they don't get freed on drop, and the renderer's still around
+		for image in removed {
+			for frame in image.frames {
+				unsafe { frame.destroy() };
+			}
+		}
 
-				self.curr_index = Some(idx);
+		if self.curr_index.is_none() {
+			if self.images.is_empty() {
+				ui.update_image("");
+			} else {
+				let idx = rng.gen_range(0, self.images.len());
+				ui.update_image(&self.images[idx].name);
+				self.set_image(idx);
 			}
 		}
 	}
 
+	// Ok this isn't actually "random image" but it's not being used anywhere else so it stays like this for now
+	pub fn random_image<S: UiLayout>(&mut self, rng: &mut Random, ui: &mut S) {
+		if self.full_auto && !self.locked && !self.images.is_empty() {
+			let idx = rng.gen_range(0, self.images.len());
+			ui.update_image(&self.images[idx].name);
+
+			self.set_image(idx);
+		}
+	}
+
+	pub fn show_image<T: AsRef<str>, S: UiLayout>(&mut self, name: T, ui: &mut S) -> Result<()> {
+		let idx = self.images
+			.iter()
+			.position(|image| image.name == name.as_ref())
+			.ok_or("No image.")?;
+		ui.update_image(&self.images[idx].name);
+
+		self.set_image(idx);
+		Ok(())
+	}
+
 	pub fn prev_image<S: UiLayout>(&mut self, ui: &mut S) {
+		if self.images.is_empty() {
+			return;
+		}
 		let length = self.images.len();
 		let idx = self.curr_index
 			.map_or(0, move |index| (index + length - 1) % length);
 		ui.update_image(&self.images[idx].name);
 
-		self.curr_index = Some(idx);
+		self.set_image(idx);
 		self.full_auto = false;
 	}
 
 	pub fn next_image<S: UiLayout>(&mut self, ui: &mut S) {
+		if self.images.is_empty() {
+			return;
+		}
 		let length = self.images.len();
 		let idx = self.curr_index.map_or(0, move |index| (index + 1) % length);
 		ui.update_image(&self.images[idx].name);
 
-		self.curr_index = Some(idx);
+		self.set_image(idx);
 		self.full_auto = false;
 	}
 
+	pub fn images(&self) -> &[Image] {
+		&self.images
+	}
+
+	pub fn current_image(&self) -> Option<&Image> {
+		self.curr_index.map(|index| &self.images[index])
+	}
+
+	// Picked by hand, so it stays until it's unlocked
+	pub fn lock_image<S: UiLayout>(&mut self, idx: usize, ui: &mut S) {
+		ui.update_image(&self.images[idx].name);
+		self.set_image(idx);
+		self.locked = true;
+	}
+
+	fn set_image(&mut self, idx: usize) {
+		// Restart the animation unless it's the same one
+		if self.curr_index != Some(idx) {
+			self.anim_start = clock::now();
+		}
+		self.curr_index = Some(idx);
+	}
+
+	// Position in the song measured in beats, used for animations synced to the beat
+	pub fn update_beat(&mut self, beat: f64) {
+		self.beat = beat;
+	}
+
 	pub fn toggle_full_auto<S: UiLayout>(&mut self, ui: &mut S) {
-		self.full_auto = !self.full_auto;
+		let full_auto = !self.full_auto;
+		self.set_full_auto(full_auto, ui);
+	}
+
+	pub fn set_full_auto<S: UiLayout>(&mut self, full_auto: bool, ui: &mut S) {
+		self.full_auto = full_auto;
 
 		ui.update_mode(self.full_auto);
 	}
 
-	pub fn draw_image<S: UiLayout>(&mut self, canvas: &mut Canvas, ui: &mut S) {
+	pub fn draw_image<T: RenderTarget, S: UiLayout>(&mut self, canvas: &mut Canvas<T>, ui: &mut S) {
 		if let Some(index) = self.curr_index {
-			self.images[index].draw(&mut self.blur, canvas, ui).unwrap();
+			let image = &mut self.images[index];
+			let frame = image.frame_index(clock::elapsed(self.anim_start), self.beat);
+
+			if self.slices.as_ref().is_some_and(Slices::finished) {
+				self.slices = None;
+			}
+			if let Some(ref slices) = self.slices {
+				image.draw_sliced(frame, slices, canvas).unwrap();
+			} else {
+				image.draw(frame, &mut self.blur, canvas, ui).unwrap();
+			}
 		}
 	}
 
+	// Length is in seconds
+	pub fn slice(&mut self, slice: Slice, length: f64, rng: &mut Random) {
+		self.slices = Some(Slices::new(slice, length, rng));
+	}
+
+	// For when the layout changes
+	pub fn update_ui<S: UiLayout>(&self, ui: &mut S) {
+		ui.update_mode(self.full_auto);
+		if let Some(idx) = self.curr_index {
+			ui.update_image(&self.images[idx].name);
+		}
+	}
+
+	pub fn toggle_lock(&mut self) {
+		self.locked = !self.locked;
+	}
+
+	pub fn toggle_blur(&mut self) {
+		self.blur_enabled = !self.blur_enabled;
+	}
+
 	pub fn blur_x<T: UiLayout>(&mut self, ui: &mut T) {
-		self.blur.blur_x(ui);
+		if self.blur_enabled {
+			self.blur.blur_x(ui);
+		}
 	}
 
 	pub fn blur_y<T: UiLayout>(&mut self, ui: &mut T) {
-		self.blur.blur_y(ui);
+		if self.blur_enabled {
+			self.blur.blur_y(ui);
+		}
+	}
+}
+
+// Horizontal position of images that don't fill the screen
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub enum Align {
+	Left,
+	#[default]
+	Centre,
+	Right,
+}
+
+impl Align {
+	pub fn from_str(align: &str) -> Option<Self> {
+		match align.trim() {
+			"left" => Some(Align::Left),
+			"center" | "centre" => Some(Align::Centre),
+			"right" => Some(Align::Right),
+			_ => None,
+		}
+	}
+
+	// As it's written in images.xml
+	pub fn name(&self) -> &'static str {
+		match *self {
+			Align::Left => "left",
+			Align::Centre => "center",
+			Align::Right => "right",
+		}
 	}
 }
 
 // Image
-struct Image {
+pub struct Image {
 	name: String,
-	image: Texture,
+	pack: PathBuf,
+	frames: Vec<Texture>,
+	// If there are less durations than frames, the last one is used for the rest
+	frame_durations: Vec<Duration>,
+	// Play the whole animation over this many beats instead of using the durations
+	beats_per_anim: Option<f64>,
+	align: Align,
 	fullname: Option<String>,
 	source: Option<String>,
 	source_other: Option<String>,
 }
 
 impl Image {
-	fn from_loader(loader: ImageLoader, texture: Texture) -> Self {
+	fn from_loader(pack: &Path, loader: ImageLoader, textures: Vec<Texture>) -> Self {
 		Image {
 			name: loader.name,
-			image: texture,
+			pack: pack.to_owned(),
+			frames: textures,
+			frame_durations: loader.frame_durations,
+			beats_per_anim: loader.beats_per_anim,
+			align: loader.align,
 			fullname: loader.fullname,
 			source: loader.source,
 			source_other: loader.source_other,
 		}
 	}
 
-	// TODO: align
-	fn draw<S: UiLayout>(
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn fullname(&self) -> Option<&str> {
+		self.fullname.as_ref().map(String::as_ref)
+	}
+
+	pub fn source(&self) -> Option<&str> {
+		self.source.as_ref().map(String::as_ref)
+	}
+
+	pub fn source_other(&self) -> Option<&str> {
+		self.source_other.as_ref().map(String::as_ref)
+	}
+
+	pub fn pack(&self) -> &Path {
+		&self.pack
+	}
+
+	fn frame_duration(&self, frame: usize) -> Duration {
+		self.frame_durations
+			.get(frame)
+			.or_else(|| self.frame_durations.last())
+			.cloned()
+			.unwrap_or_else(|| Duration::from_millis(DEFAULT_FRAME_DURATION))
+	}
+
+	// Which frame to show - loops around
+	fn frame_index(&self, elapsed: Duration, beat: f64) -> usize {
+		let num_frames = self.frames.len();
+		if num_frames <= 1 {
+			return 0;
+		}
+
+		if let Some(beats) = self.beats_per_anim {
+			let progress = (beat / beats).fract();
+			let progress = if progress < 0.0 { progress + 1.0 } else { progress };
+			return ((progress * num_frames as f64) as usize).min(num_frames - 1);
+		}
+
+		let total: f64 = (0..num_frames).map(|frame| duration_to_secs(self.frame_duration(frame))).sum();
+		if total <= 0.0 {
+			return 0;
+		}
+
+		let mut time = duration_to_secs(elapsed) % total;
+		for frame in 0..num_frames {
+			time -= duration_to_secs(self.frame_duration(frame));
+			if time < 0.0 {
+				return frame;
+			}
+		}
+		num_frames - 1
+	}
+
+	// Fit the image inside the output without stretching, then line it up according to align
+	fn placement(&self, frame: usize, (width, height): (u32, u32)) -> Rect {
+		let TextureQuery { width: image_width, height: image_height, .. } = self.frames[frame].query();
+		if image_width == 0 || image_height == 0 {
+			return Rect::new(0, 0, width, height);
+		}
+
+		let scale = f64::min(
+			width as f64 / image_width as f64,
+			height as f64 / image_height as f64,
+		);
+		let w = ((image_width as f64 * scale) as u32).max(1);
+		let h = ((image_height as f64 * scale) as u32).max(1);
+
+		let x = match self.align {
+			Align::Left => 0,
+			Align::Centre => width.saturating_sub(w) as i32 / 2,
+			Align::Right => width.saturating_sub(w) as i32,
+		};
+		// The output can be 0x0 while the window is minimised
+		let y = height.saturating_sub(h) as i32 / 2;
+
+		Rect::new(x, y, w, h)
+	}
+
+	// Cut the image up and shift the pieces - they slide back together over the length of the slice
+	fn draw_sliced<T: RenderTarget>(&mut self, frame: usize, slices: &Slices, canvas: &mut Canvas<T>) -> Result<()> {
+		let rect = self.placement(frame, canvas.output_size()?);
+		let image = &mut self.frames[frame];
+		let TextureQuery { width, height, .. } = image.query();
+		image.set_alpha_mod(0xD0);
+
+		let factor = slices.factor();
+		for &(row_start, row_end, row_offset) in slices.rows.iter() {
+			for &(col_start, col_end, col_offset) in slices.columns.iter() {
+				let src = Rect::new(
+					(col_start * width as f64) as i32,
+					(row_start * height as f64) as i32,
+					(((col_end - col_start) * width as f64) as u32).max(1),
+					(((row_end - row_start) * height as f64) as u32).max(1),
+				);
+				let dst = Rect::new(
+					rect.x() + (col_start * rect.width() as f64 + row_offset * factor * rect.width() as f64) as i32,
+					rect.y() + (row_start * rect.height() as f64 + col_offset * factor * rect.height() as f64) as i32,
+					(((col_end - col_start) * rect.width() as f64) as u32).max(1),
+					(((row_end - row_start) * rect.height() as f64) as u32).max(1),
+				);
+				canvas.copy(image, Some(src), Some(dst))?;
+			}
+		}
+		Ok(())
+	}
+
+	fn draw<T: RenderTarget, S: UiLayout>(
 		&mut self,
+		frame: usize,
 		blur: &mut Blur,
-		canvas: &mut Canvas,
+		canvas: &mut Canvas<T>,
 		ui: &mut S,
 	) -> Result<()> {
+		let (width, height) = canvas.output_size()?;
+		let rect = self.placement(frame, (width, height));
+		let image = &mut self.frames[frame];
 		match blur.blur_type {
 			BlurType::Horizontal => {
-				self.image.set_alpha_mod(0xFF / blur.num);
+				image.set_alpha_mod(0xFF / blur.num);
 
 				let factor = blur.factor();
-				let dist = blur.dist * factor;
+				let dist = blur.dist * factor * height as f64;
 
 				for x in (0..blur.num).map(|i| 2.0 * i as f64 / (blur.num as f64 - 1.0) - 1.0) {
-					let rect = Rect::new((x * dist) as i32, 0, 1280, 720);
-					canvas.copy(&self.image, None, Some(rect))?;
+					let mut rect = rect;
+					rect.offset((x * dist) as i32, 0);
+					canvas.copy(image, None, Some(rect))?;
 				}
 
 				if dist < 1.0 {
@@ -146,14 +435,15 @@ impl Image {
 				}
 			}
 			BlurType::Vertical => {
-				self.image.set_alpha_mod(0xFF / blur.num);
+				image.set_alpha_mod(0xFF / blur.num);
 
 				let factor = blur.factor();
-				let dist = blur.dist * factor;
+				let dist = blur.dist * factor * height as f64;
 
 				for y in (0..blur.num).map(|i| 2.0 * i as f64 / (blur.num as f64 - 1.0) - 1.0) {
-					let rect = Rect::new(0, (y * dist) as i32, 1280, 720);
-					canvas.copy(&self.image, None, Some(rect))?;
+					let mut rect = rect;
+					rect.offset(0, (y * dist) as i32);
+					canvas.copy(image, None, Some(rect))?;
 				}
 
 				if dist < 1.0 {
@@ -164,14 +454,65 @@ impl Image {
 				}
 			}
 			BlurType::None => {
-				self.image.set_alpha_mod(0xD0);
-				canvas.copy(&self.image, None, None)?;
+				image.set_alpha_mod(0xD0);
+				canvas.copy(image, None, Some(rect))?;
 			}
 		}
 		Ok(())
 	}
 }
 
+// Slices
+struct Slices {
+	init: Instant,
+	length: f64, // seconds
+	// Start, end (as a fraction of the image) and how far the piece gets moved
+	// Rows get moved sideways, columns up and down
+	rows: Vec<(f64, f64, f64)>,
+	columns: Vec<(f64, f64, f64)>,
+}
+
+impl Slices {
+	fn new(slice: Slice, length: f64, rng: &mut Random) -> Self {
+		let (rows, columns) = match slice {
+			Slice::Horizontal => (random_cuts(rng), whole()),
+			Slice::Vertical => (whole(), random_cuts(rng)),
+			Slice::Both => (random_cuts(rng), random_cuts(rng)),
+		};
+
+		Slices {
+			init: clock::now(),
+			length,
+			rows,
+			columns,
+		}
+	}
+
+	fn factor(&self) -> f64 {
+		(1.0 - duration_to_secs(clock::elapsed(self.init)) / self.length).max(0.0)
+	}
+
+	fn finished(&self) -> bool {
+		duration_to_secs(clock::elapsed(self.init)) >= self.length
+	}
+}
+
+fn whole() -> Vec<(f64, f64, f64)> {
+	vec![(0.0, 1.0, 0.0)]
+}
+
+fn random_cuts(rng: &mut Random) -> Vec<(f64, f64, f64)> {
+	let num = rng.gen_range(4, 12);
+	let mut cuts: Vec<f64> = (0..num).map(|_| rng.gen_range(0.0, 1.0)).collect();
+	cuts.push(0.0);
+	cuts.push(1.0);
+	cuts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+	cuts.windows(2)
+		.map(|cut| (cut[0], cut[1], rng.gen_range(-0.1, 0.1)))
+		.collect()
+}
+
 // Blur
 struct Blur {
 	blur_type: BlurType,
@@ -189,8 +530,8 @@ enum BlurType {
 impl Blur {
 	fn blur_x<T: UiLayout>(&mut self, ui: &mut T) {
 		self.blur_type = BlurType::Horizontal;
-		self.dist = 40.0;
-		self.init = Instant::now();
+		self.dist = BLUR_DISTANCE;
+		self.init = clock::now();
 
 		ui.update_x_blur(1.0);
 		ui.update_y_blur(0.0);
@@ -198,8 +539,8 @@ impl Blur {
 
 	fn blur_y<T: UiLayout>(&mut self, ui: &mut T) {
 		self.blur_type = BlurType::Vertical;
-		self.dist = 40.0;
-		self.init = Instant::now();
+		self.dist = BLUR_DISTANCE;
+		self.init = clock::now();
 
 		ui.update_x_blur(0.0);
 		ui.update_y_blur(1.0);
@@ -207,15 +548,15 @@ impl Blur {
 
 	fn factor(&self) -> f64 {
 		// blur decay rate
-		(-15.0 * duration_to_secs(self.init.elapsed())).exp()
+		(-15.0 * duration_to_secs(clock::elapsed(self.init))).exp()
 	}
 
 	fn new(num: u8) -> Self {
 		Blur {
 			blur_type: BlurType::None,
 			num,
-			dist: 20.0,
-			init: Instant::now(),
+			dist: BLUR_DISTANCE,
+			init: clock::now(),
 		}
 	}
 }
@@ -0,0 +1,158 @@
+// Named actions and the keys that trigger them
+// Keys can be rebound in the [keys] table of config.toml, e.g. next_song = "L"
+// Key names are SDL's scancode names: https://wiki.libsdl.org/SDL_Scancode
+
+use std::collections::HashMap;
+
+use sdl2::keyboard::Scancode;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+	ToggleFullAuto,
+	PrevSong,
+	NextSong,
+	RandomSong,
+	PrevImage,
+	NextImage,
+	LockImage,
+	SongBrowser,
+	ImageBrowser,
+	Editor,
+	Pause,
+	PrevBeat,
+	NextBeat,
+	PrevLoop,
+	NextLoop,
+	RestartBuildup,
+	VolumeDown,
+	VolumeUp,
+	Mute,
+	ToggleBlur,
+	ToggleUi,
+	NextLayout,
+	UnloadPack,
+	ReloadPacks,
+	ToggleFullscreen,
+	Credits,
+	Help,
+	Quit,
+}
+
+// Action, name in the config, default key, description for the help overlay
+pub static ACTIONS: [(Action, &str, &str, &str); 28] = [
+	(Action::ToggleFullAuto, "toggle_full_auto", "F", "Toggle full auto"),
+	(Action::PrevSong, "prev_song", "J", "Previous song"),
+	(Action::NextSong, "next_song", "K", "Next song"),
+	(Action::RandomSong, "random_song", "R", "Random song"),
+	(Action::PrevImage, "prev_image", "N", "Previous image"),
+	(Action::NextImage, "next_image", "M", "Next image"),
+	(Action::LockImage, "lock_image", "L", "Lock image"),
+	(Action::SongBrowser, "song_browser", "S", "Browse songs"),
+	(Action::ImageBrowser, "image_browser", "I", "Browse images"),
+	(Action::Editor, "editor", "E", "Edit the current song's beats"),
+	(Action::Pause, "pause", "Space", "Pause/resume"),
+	(Action::PrevBeat, "prev_beat", "Left", "Back a beat"),
+	(Action::NextBeat, "next_beat", "Right", "Forward a beat"),
+	(Action::PrevLoop, "prev_loop", "[", "Back a loop"),
+	(Action::NextLoop, "next_loop", "]", "Forward a loop"),
+	(Action::RestartBuildup, "restart_buildup", "Backspace", "Restart the buildup"),
+	(Action::VolumeDown, "volume_down", "-", "Volume down"),
+	(Action::VolumeUp, "volume_up", "=", "Volume up"),
+	(Action::Mute, "mute", "0", "Mute"),
+	(Action::ToggleBlur, "toggle_blur", "B", "Toggle blur"),
+	(Action::ToggleUi, "toggle_ui", "U", "Toggle UI"),
+	(Action::NextLayout, "next_layout", "Tab", "Next UI layout"),
+	(Action::UnloadPack, "unload_pack", "Delete", "Unload the current song's pack"),
+	(Action::ReloadPacks, "reload_packs", "F5", "Load new and unloaded packs"),
+	(Action::ToggleFullscreen, "toggle_fullscreen", "F11", "Toggle fullscreen"),
+	(Action::Credits, "credits", "C", "Show credits, click a link to copy it"),
+	(Action::Help, "help", "H", "Show this help"),
+	(Action::Quit, "quit", "Escape", "Quit"),
+];
+
+pub struct Keymap {
+	keys: HashMap<Scancode, Action>,
+}
+
+impl Keymap {
+	// Starts from the defaults, then applies whatever's in the config
+	// A key from the config that would take over another action's key is ignored
+	pub fn new(bindings: Option<&HashMap<String, String>>) -> Self {
+		// Action, name, default key, key from the config
+		let mut keys: Vec<(Action, &str, Scancode, Option<Scancode>)> = ACTIONS
+			.iter()
+			.map(|&(action, name, key, _)| (action, name, Scancode::from_name(key).expect("Bad default key"), None))
+			.collect();
+
+		if let Some(bindings) = bindings {
+			for (name, key) in bindings {
+				let entry = match keys.iter_mut().find(|&&mut (_, action_name, _, _)| action_name == name) {
+					Some(entry) => entry,
+					None => {
+						eprintln!("Warning: Unknown action in keys: {}", name);
+						continue;
+					}
+				};
+				match Scancode::from_name(key) {
+					Some(scancode) => entry.3 = Some(scancode),
+					None => eprintln!("Warning: Unknown key for {}: {}", name, key),
+				}
+			}
+		}
+
+		// Putting an action back to its default can clash with something else, so go until nothing does
+		// The defaults don't clash with each other, so this always finishes
+		loop {
+			let chosen: Vec<Scancode> = keys.iter().map(|&(_, _, default, key)| key.unwrap_or(default)).collect();
+			let clash = (0..keys.len()).find(|&i| {
+				keys[i].3.is_some() && chosen.iter().enumerate().any(|(j, &other)| i != j && other == chosen[i])
+			});
+			match clash {
+				Some(i) => {
+					let (_, name, default, key) = keys[i];
+					let key = key.map_or("", Scancode::name);
+					eprintln!("Warning: {} for {} is already used, keeping {}", key, name, default.name());
+					keys[i].3 = None;
+				}
+				None => break,
+			}
+		}
+
+		Keymap {
+			keys: keys.into_iter().map(|(action, _, default, key)| (key.unwrap_or(default), action)).collect(),
+		}
+	}
+
+	pub fn action(&self, scancode: Scancode) -> Option<Action> {
+		self.keys.get(&scancode).cloned()
+	}
+
+	pub fn key(&self, action: Action) -> Option<Scancode> {
+		self.keys
+			.iter()
+			.find(|&(_, &bound)| bound == action)
+			.map(|(&scancode, _)| scancode)
+	}
+
+	// Key name and description for each action, in the same order as ACTIONS
+	pub fn help_lines(&self) -> Vec<(String, &'static str)> {
+		ACTIONS
+			.iter()
+			.map(|&(action, _, _, description)| {
+				let key = self.key(action).map_or("(none)", Scancode::name);
+				(key.to_string(), description)
+			})
+			.collect()
+	}
+
+	// The [keys] table for config.toml
+	pub fn to_config(&self) -> String {
+		let mut config = String::from("[keys]\n");
+		for &(action, name, _, _) in ACTIONS.iter() {
+			if let Some(scancode) = self.key(action) {
+				config.push_str(&format!("{} = \"{}\"\n", name, scancode.name()));
+			}
+		}
+		config
+	}
+}
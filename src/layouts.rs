@@ -0,0 +1,545 @@
+// The other layouts, loosely after the ones in 0x40-web
+// BasicUi in ui.rs is the retro one
+
+use sdl2::pixels::Color as Colour;
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
+use sdl2::rect::Rect;
+use sdl2::ttf::Font;
+
+use Result;
+use songs::{Song, BeatIndex};
+use glyphs::GlyphAtlas;
+use ui::{layout_scale, upcoming_beats, BasicUi, HexNum, TextUi, UiLayout, LAYOUT_HEIGHT, LAYOUT_WIDTH};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum LayoutKind {
+	Basic,
+	Modern,
+	Minimal,
+	Weed,
+	XMas,
+}
+
+impl LayoutKind {
+	pub fn from_str(name: &str) -> Option<Self> {
+		match name.to_lowercase().as_ref() {
+			"basic" | "retro" => Some(LayoutKind::Basic),
+			"modern" => Some(LayoutKind::Modern),
+			"minimal" => Some(LayoutKind::Minimal),
+			"weed" => Some(LayoutKind::Weed),
+			"xmas" => Some(LayoutKind::XMas),
+			_ => None,
+		}
+	}
+
+	pub fn next(self) -> Self {
+		match self {
+			LayoutKind::Basic => LayoutKind::Modern,
+			LayoutKind::Modern => LayoutKind::Minimal,
+			LayoutKind::Minimal => LayoutKind::Weed,
+			LayoutKind::Weed => LayoutKind::XMas,
+			LayoutKind::XMas => LayoutKind::Basic,
+		}
+	}
+}
+
+// Whichever layout is being shown
+pub enum Layout<'a, Target: 'a> {
+	Basic(BasicUi<'a, Target>),
+	Modern(ModernUi<'a, Target>),
+	Minimal(MinimalUi<'a, Target>),
+	Weed(WeedUi<'a, Target>),
+	XMas(XMasUi<'a, Target>),
+}
+
+impl<'a, Target> Layout<'a, Target> {
+	// Starts out blank, the managers' update_ui fill it in
+	pub fn new(
+		kind: LayoutKind,
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Self {
+		match kind {
+			LayoutKind::Basic => Layout::Basic(BasicUi::new(font, texture_creator)),
+			LayoutKind::Modern => Layout::Modern(ModernUi::new(font, texture_creator)),
+			LayoutKind::Minimal => Layout::Minimal(MinimalUi::new(font, texture_creator)),
+			LayoutKind::Weed => Layout::Weed(WeedUi::new(font, texture_creator)),
+			LayoutKind::XMas => Layout::XMas(XMasUi::new(font, texture_creator)),
+		}
+	}
+
+	pub fn kind(&self) -> LayoutKind {
+		match *self {
+			Layout::Basic(_) => LayoutKind::Basic,
+			Layout::Modern(_) => LayoutKind::Modern,
+			Layout::Minimal(_) => LayoutKind::Minimal,
+			Layout::Weed(_) => LayoutKind::Weed,
+			Layout::XMas(_) => LayoutKind::XMas,
+		}
+	}
+}
+
+macro_rules! each_layout {
+	($layout:expr, $ui:ident => $body:expr) => {
+		match *$layout {
+			Layout::Basic(ref mut $ui) => $body,
+			Layout::Modern(ref mut $ui) => $body,
+			Layout::Minimal(ref mut $ui) => $body,
+			Layout::Weed(ref mut $ui) => $body,
+			Layout::XMas(ref mut $ui) => $body,
+		}
+	};
+}
+
+impl<'a, Target> UiLayout for Layout<'a, Target> {
+	fn update_mode(&mut self, full_auto: bool) {
+		each_layout!(self, ui => ui.update_mode(full_auto))
+	}
+	fn update_time(&mut self, time: i32) {
+		each_layout!(self, ui => ui.update_time(time))
+	}
+	fn update_beat(&mut self, beat: BeatIndex) {
+		each_layout!(self, ui => ui.update_beat(beat))
+	}
+	fn update_image(&mut self, image_name: &str) {
+		each_layout!(self, ui => ui.update_image(image_name))
+	}
+	fn update_colour(&mut self, index: usize, name: &str) {
+		each_layout!(self, ui => ui.update_colour(index, name))
+	}
+	fn update_x_blur(&mut self, x: f64) {
+		each_layout!(self, ui => ui.update_x_blur(x))
+	}
+	fn update_y_blur(&mut self, y: f64) {
+		each_layout!(self, ui => ui.update_y_blur(y))
+	}
+	fn update_song(&mut self, song: &Song) {
+		each_layout!(self, ui => ui.update_song(song))
+	}
+
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		each_layout!(self, ui => ui.draw(canvas))
+	}
+}
+
+fn text_at<Target>(atlas: &mut GlyphAtlas<Target>, text: &str, x: i32, y: i32) -> TextUi {
+	let mut text = TextUi::create(text, atlas).unwrap();
+	text.set_pos(x, y);
+	text
+}
+
+// Layout coordinates, centred along the bottom of the output
+fn bottom_centre(width: u32, height: u32) -> (f64, (i32, i32)) {
+	let scale = layout_scale(width, height);
+	let origin = (
+		(width as i32 - (LAYOUT_WIDTH as f64 * scale) as i32) / 2,
+		height as i32 - (LAYOUT_HEIGHT as f64 * scale) as i32,
+	);
+	(scale, origin)
+}
+
+//
+
+// A bar along the bottom with the names in the middle
+pub struct ModernUi<'a, Target: 'a> {
+	atlas: GlyphAtlas<'a, Target>,
+
+	timer_text: TextUi,
+	beat_text: TextUi,
+	mode_text: TextUi,
+	colour_text: TextUi,
+
+	song_text: TextUi,
+	image_text: TextUi,
+
+	rhythm_marker: TextUi,
+	rhythm_text: TextUi,
+
+	buildup_rhythm: Vec<char>,
+	rhythm: Vec<char>,
+	beat: Option<BeatIndex>,
+}
+
+const MODERN_BAR_TOP: i32 = 668;
+
+impl<'a, Target> ModernUi<'a, Target> {
+	pub fn new(
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Self {
+		let mut atlas = GlyphAtlas::new(font, texture_creator).unwrap();
+
+		let mut ui = ModernUi {
+			timer_text: text_at(&mut atlas, "T=$0x00000", 8, MODERN_BAR_TOP + 8),
+			beat_text: text_at(&mut atlas, "B=$0x0000", 8, MODERN_BAR_TOP + 24),
+			mode_text: text_at(&mut atlas, "FULL AUTO", 0, MODERN_BAR_TOP + 24),
+			colour_text: text_at(&mut atlas, "BLACK", 0, MODERN_BAR_TOP + 8),
+
+			song_text: text_at(&mut atlas, " ", 0, MODERN_BAR_TOP + 8),
+			image_text: text_at(&mut atlas, " ", 0, MODERN_BAR_TOP + 24),
+
+			rhythm_marker: text_at(&mut atlas, ">", 0, MODERN_BAR_TOP + 40),
+			rhythm_text: text_at(&mut atlas, " ", LAYOUT_WIDTH as i32 / 2, MODERN_BAR_TOP + 40),
+
+			buildup_rhythm: Vec::new(),
+			rhythm: Vec::new(),
+			beat: None,
+
+			atlas,
+		};
+		ui.align_text();
+		ui
+	}
+
+	// Names in the middle, mode and colour on the right
+	fn align_text(&mut self) {
+		let right = LAYOUT_WIDTH as i32 - 8;
+		let centre = |text: &TextUi| (LAYOUT_WIDTH as i32 - text.width() as i32) / 2;
+
+		let x = right - self.mode_text.width() as i32;
+		self.mode_text.set_pos(x, MODERN_BAR_TOP + 24);
+		let x = right - self.colour_text.width() as i32;
+		self.colour_text.set_pos(x, MODERN_BAR_TOP + 8);
+
+		let x = centre(&self.song_text);
+		self.song_text.set_pos(x, MODERN_BAR_TOP + 8);
+		let x = centre(&self.image_text);
+		self.image_text.set_pos(x, MODERN_BAR_TOP + 24);
+
+		let x = LAYOUT_WIDTH as i32 / 2 - self.rhythm_marker.width() as i32;
+		self.rhythm_marker.set_pos(x, MODERN_BAR_TOP + 40);
+	}
+}
+
+impl<'a, Target> UiLayout for ModernUi<'a, Target> {
+	fn update_mode(&mut self, full_auto: bool) {
+		let text = if full_auto { "FULL AUTO" } else { "NORMAL" };
+		self.mode_text.set_text(text, &mut self.atlas).unwrap();
+		self.align_text();
+	}
+
+	fn update_time(&mut self, time: i32) {
+		self.timer_text.set_text(format!("T={:5}", HexNum(time)), &mut self.atlas).unwrap();
+	}
+
+	fn update_beat(&mut self, beat: BeatIndex) {
+		// Gets called every frame, but only changes on the beat
+		if self.beat == Some(beat) {
+			return;
+		}
+		self.beat = Some(beat);
+
+		// Only enough to get to the edge
+		let (beat_string, index) = upcoming_beats(&self.rhythm, &self.buildup_rhythm, beat, 80);
+		self.rhythm_text.set_text(beat_string, &mut self.atlas).unwrap();
+		self.beat_text.set_text(format!("B={:4}", HexNum(index)), &mut self.atlas).unwrap();
+	}
+
+	fn update_image(&mut self, image_name: &str) {
+		self.image_text.set_text(image_name.to_uppercase(), &mut self.atlas).unwrap();
+		self.align_text();
+	}
+
+	fn update_colour(&mut self, _index: usize, name: &str) {
+		self.colour_text.set_text(name.to_uppercase(), &mut self.atlas).unwrap();
+		self.align_text();
+	}
+
+	fn update_x_blur(&mut self, _x: f64) {}
+	fn update_y_blur(&mut self, _y: f64) {}
+
+	fn update_song(&mut self, song: &Song) {
+		self.song_text.set_text(song.title.to_uppercase(), &mut self.atlas).unwrap();
+		self.align_text();
+
+		self.buildup_rhythm = song.buildup_rhythm.clone();
+		self.rhythm = song.rhythm.clone();
+		self.beat = None;
+	}
+
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		let (width, height) = canvas.output_size()?;
+		let (scale, origin) = bottom_centre(width, height);
+
+		// The bar goes all the way across
+		let top = origin.1 + (MODERN_BAR_TOP as f64 * scale) as i32;
+		canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xA0));
+		canvas.fill_rect(Rect::new(0, top, width, (height as i32 - top).max(1) as u32))?;
+
+		self.timer_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.beat_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.mode_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.colour_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		self.song_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.image_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		self.rhythm_marker.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.rhythm_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		Ok(())
+	}
+}
+
+//
+
+// Just the song and image names in the corner
+pub struct MinimalUi<'a, Target: 'a> {
+	atlas: GlyphAtlas<'a, Target>,
+
+	song_text: TextUi,
+	image_text: TextUi,
+}
+
+impl<'a, Target> MinimalUi<'a, Target> {
+	pub fn new(
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Self {
+		let mut atlas = GlyphAtlas::new(font, texture_creator).unwrap();
+		let mut ui = MinimalUi {
+			song_text: text_at(&mut atlas, " ", 0, 0),
+			image_text: text_at(&mut atlas, " ", 0, 0),
+
+			atlas,
+		};
+		ui.align_text();
+		ui
+	}
+
+	fn align_text(&mut self) {
+		let right = LAYOUT_WIDTH as i32 - 8;
+		let x = right - self.song_text.width() as i32;
+		self.song_text.set_pos(x, 688);
+		let x = right - self.image_text.width() as i32;
+		self.image_text.set_pos(x, 702);
+	}
+}
+
+impl<'a, Target> UiLayout for MinimalUi<'a, Target> {
+	fn update_mode(&mut self, _full_auto: bool) {}
+	fn update_time(&mut self, _time: i32) {}
+	fn update_beat(&mut self, _beat: BeatIndex) {}
+
+	fn update_image(&mut self, image_name: &str) {
+		self.image_text
+			.set_text(image_name.to_uppercase(), &mut self.atlas)
+			.unwrap();
+		self.align_text();
+	}
+
+	fn update_colour(&mut self, _index: usize, _name: &str) {}
+	fn update_x_blur(&mut self, _x: f64) {}
+	fn update_y_blur(&mut self, _y: f64) {}
+
+	fn update_song(&mut self, song: &Song) {
+		self.song_text
+			.set_text(song.title.to_uppercase(), &mut self.atlas)
+			.unwrap();
+		self.align_text();
+	}
+
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		// Bottom right corner
+		let (width, height) = canvas.output_size()?;
+		let scale = layout_scale(width, height);
+		let origin = (
+			width as i32 - (LAYOUT_WIDTH as f64 * scale) as i32,
+			height as i32 - (LAYOUT_HEIGHT as f64 * scale) as i32,
+		);
+
+		self.song_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.image_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		Ok(())
+	}
+}
+
+//
+
+// The beats run up the side of the screen
+pub struct WeedUi<'a, Target: 'a> {
+	atlas: GlyphAtlas<'a, Target>,
+
+	beat_text: TextUi,
+	song_text: TextUi,
+	image_text: TextUi,
+
+	rhythm_marker: TextUi,
+	rhythm_text: TextUi,
+
+	buildup_rhythm: Vec<char>,
+	rhythm: Vec<char>,
+	beat: Option<BeatIndex>,
+}
+
+// Bottom of the vertical rhythm
+const WEED_RHYTHM_BOTTOM: i32 = 660;
+
+impl<'a, Target> WeedUi<'a, Target> {
+	pub fn new(
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Self {
+		let mut atlas = GlyphAtlas::new(font, texture_creator).unwrap();
+
+		let rhythm_marker = text_at(&mut atlas, ">>", 8, WEED_RHYTHM_BOTTOM);
+		let marker_width = rhythm_marker.width() as i32;
+
+		WeedUi {
+			beat_text: text_at(&mut atlas, "B=$0x0000", 8, 672),
+			song_text: text_at(&mut atlas, " ", 8, 688),
+			image_text: text_at(&mut atlas, " ", 8, 702),
+
+			rhythm_marker,
+			rhythm_text: text_at(&mut atlas, " ", 8, WEED_RHYTHM_BOTTOM - marker_width),
+
+			buildup_rhythm: Vec::new(),
+			rhythm: Vec::new(),
+			beat: None,
+
+			atlas,
+		}
+	}
+}
+
+impl<'a, Target> UiLayout for WeedUi<'a, Target> {
+	fn update_mode(&mut self, _full_auto: bool) {}
+	fn update_time(&mut self, _time: i32) {}
+
+	fn update_beat(&mut self, beat: BeatIndex) {
+		if self.beat == Some(beat) {
+			return;
+		}
+		self.beat = Some(beat);
+
+		let (beat_string, index) = upcoming_beats(&self.rhythm, &self.buildup_rhythm, beat, 64);
+		self.rhythm_text
+			.set_text(beat_string, &mut self.atlas)
+			.unwrap();
+		self.beat_text
+			.set_text(format!("B={:4}", HexNum(index)), &mut self.atlas)
+			.unwrap();
+	}
+
+	fn update_image(&mut self, image_name: &str) {
+		self.image_text
+			.set_text(image_name.to_uppercase(), &mut self.atlas)
+			.unwrap();
+	}
+
+	fn update_colour(&mut self, _index: usize, _name: &str) {}
+	fn update_x_blur(&mut self, _x: f64) {}
+	fn update_y_blur(&mut self, _y: f64) {}
+
+	fn update_song(&mut self, song: &Song) {
+		self.song_text
+			.set_text(song.title.to_uppercase(), &mut self.atlas)
+			.unwrap();
+
+		self.buildup_rhythm = song.buildup_rhythm.clone();
+		self.rhythm = song.rhythm.clone();
+		self.beat = None;
+	}
+
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		// Bottom left, like the basic one
+		let (width, height) = canvas.output_size()?;
+		let scale = layout_scale(width, height);
+		let origin = (0, height as i32 - (LAYOUT_HEIGHT as f64 * scale) as i32);
+
+		self.beat_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.song_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.image_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		self.rhythm_marker.draw_vertical_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.rhythm_text.draw_vertical_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		Ok(())
+	}
+}
+
+//
+
+// The modern one with a string of lights along the top that flash on the beat
+pub struct XMasUi<'a, Target: 'a> {
+	modern: ModernUi<'a, Target>,
+	beat: usize,
+}
+
+const XMAS_LIGHTS: i32 = 24;
+const XMAS_COLOURS: [Colour; 4] = [
+	Colour { r: 0xE0, g: 0x10, b: 0x10, a: 0xFF },
+	Colour { r: 0x10, g: 0xC0, b: 0x10, a: 0xFF },
+	Colour { r: 0xF0, g: 0xD0, b: 0x10, a: 0xFF },
+	Colour { r: 0x10, g: 0x40, b: 0xE0, a: 0xFF },
+];
+
+impl<'a, Target> XMasUi<'a, Target> {
+	pub fn new(
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Self {
+		let mut modern = ModernUi::new(font, texture_creator);
+		modern.song_text.set_colour(XMAS_COLOURS[0]);
+		modern.image_text.set_colour(XMAS_COLOURS[1]);
+
+		XMasUi { modern, beat: 0 }
+	}
+}
+
+impl<'a, Target> UiLayout for XMasUi<'a, Target> {
+	fn update_mode(&mut self, full_auto: bool) {
+		self.modern.update_mode(full_auto);
+	}
+	fn update_time(&mut self, time: i32) {
+		self.modern.update_time(time);
+	}
+	fn update_beat(&mut self, beat: BeatIndex) {
+		self.beat = match beat {
+			BeatIndex::Loop(idx) | BeatIndex::Buildup(idx) => idx,
+		};
+		self.modern.update_beat(beat);
+	}
+	fn update_image(&mut self, image_name: &str) {
+		self.modern.update_image(image_name);
+	}
+	fn update_colour(&mut self, index: usize, name: &str) {
+		self.modern.update_colour(index, name);
+	}
+	fn update_x_blur(&mut self, x: f64) {
+		self.modern.update_x_blur(x);
+	}
+	fn update_y_blur(&mut self, y: f64) {
+		self.modern.update_y_blur(y);
+	}
+	fn update_song(&mut self, song: &Song) {
+		self.modern.update_song(song);
+	}
+
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		self.modern.draw(canvas)?;
+
+		let (width, height) = canvas.output_size()?;
+		let scale = layout_scale(width, height);
+		let size = ((12.0 * scale) as u32).max(2);
+		let spacing = width as i32 / XMAS_LIGHTS;
+
+		// Wire
+		canvas.set_draw_color(Colour::RGB(0x10, 0x40, 0x10));
+		canvas.fill_rect(Rect::new(0, size as i32 / 2, width, (size / 6).max(1)))?;
+
+		// Every other light is on, swapping each beat
+		for i in 0..XMAS_LIGHTS {
+			let mut colour = XMAS_COLOURS[i as usize % XMAS_COLOURS.len()];
+			if (i as usize + self.beat) % 2 == 1 {
+				colour = Colour::RGB(colour.r / 3, colour.g / 3, colour.b / 3);
+			}
+			canvas.set_draw_color(colour);
+			let x = i * spacing + (spacing - size as i32) / 2;
+			canvas.fill_rect(Rect::new(x, size as i32 / 2, size, size))?;
+		}
+
+		Ok(())
+	}
+}
@@ -2,24 +2,26 @@ extern crate xml;
 
 use std;
 
-use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::ffi::OsStr;
-use std::io::{BufReader, Cursor, Read};
+use std::io::{self, BufReader, Read};
 
 use std::sync::mpsc::Sender;
+use std::fmt;
+use std::error::Error as StdError;
 
 use std::collections::HashMap;
+use std::time::Duration;
 
-use zip::read::{ZipArchive, ZipFile};
 use loader::xml::reader::{EventReader, XmlEvent};
-use rodio::source::Source;
+use loader::xml::common::{Position, TextPosition};
 
-use sdl2::rwops::RWops;
-use sdl2::image::ImageRWops;
 //use sdl2::surface::SurfaceContext;
 
-use mp3::Mp3Decoder;
+use archive::{self, PackArchive, PackFile};
+use images::Align;
+use audio::{AudioFormat, EncodedAudio};
+use image_formats::{self, ImageFormat};
 use songs::Song;
 use surface::Surface;
 use Result;
@@ -27,11 +29,82 @@ use Result;
 pub enum LoadStatus {
 	TotalSize(u64),
 	LoadSize(u64),
+	// Something in the pack couldn't be loaded - the rest of it carries on unless it was the whole pack
+	Failed(LoadError),
 	Done(ResPack),
 }
 
+#[derive(Debug)]
+pub struct LoadError {
+	pub pack: String,
+	pub file: Option<String>,
+	pub position: Option<TextPosition>,
+	// Path of elements leading to the error, e.g. songs/song/rhythm
+	pub element: Vec<String>,
+	pub message: String,
+	pub skipped: Skipped,
+}
+
+// What got left out because of an error
+#[derive(Debug, Clone, PartialEq)]
+pub enum Skipped {
+	Pack,
+	File(String),
+	Song(String),
+	Image(String),
+	Element(String),
+	// Nothing was left out, it just isn't used by anything
+	Unused(String),
+}
+
+impl LoadError {
+	fn new<T: Into<String>>(pack: &str, file: Option<&str>, message: T, skipped: Skipped) -> Self {
+		LoadError {
+			pack: pack.to_owned(),
+			file: file.map(str::to_owned),
+			position: None,
+			element: Vec::new(),
+			message: message.into(),
+			skipped,
+		}
+	}
+}
+
+impl fmt::Display for LoadError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.pack)?;
+		if let Some(ref file) = self.file {
+			write!(f, ": {}", file)?;
+		}
+		if let Some(position) = self.position {
+			write!(f, ":{}", position)?;
+		}
+		if !self.element.is_empty() {
+			write!(f, " <{}>", self.element.join("/"))?;
+		}
+		write!(f, ": {}", self.message)?;
+
+		match self.skipped {
+			Skipped::Pack => write!(f, " (skipped pack)"),
+			Skipped::File(ref name) => write!(f, " (skipped rest of {})", name),
+			Skipped::Song(ref name) => write!(f, " (skipped song {})", name),
+			Skipped::Image(ref name) => write!(f, " (skipped image {})", name),
+			Skipped::Element(ref name) => write!(f, " (skipped <{}>)", name),
+			Skipped::Unused(ref name) => write!(f, " (unused {})", name),
+		}
+	}
+}
+
+impl StdError for LoadError {
+	fn description(&self) -> &str {
+		&self.message
+	}
+}
+
 // SDL2-rust implementation of surface isn't threadsafe for some reason
 pub struct ResPack {
+	// Where it was loaded from, which is what it gets unloaded by
+	pub path: PathBuf,
 	pub info: PackInfo,
 	pub images: Vec<ImageLoader>,
 	pub songs: Vec<Song>,
@@ -41,9 +114,16 @@ pub struct ImageLoader {
 	//data: SurfaceContext
 	pub name: String,
 	pub fullname: Option<String>,
-	pub data: Surface,
+	// Only one frame unless it's animated
+	pub frames: Vec<Surface>,
+	pub frame_durations: Vec<Duration>,
+	pub beats_per_anim: Option<f64>,
+	pub align: Align,
 	pub source: Option<String>,
 	pub source_other: Option<String>,
+	// The files it was made from as they were in the pack, so it can be written back out
+	// Dropped once it's turned into textures
+	pub files: Vec<(String, Vec<u8>)>,
 }
 
 pub struct SongData {
@@ -57,10 +137,14 @@ pub struct SongData {
 }
 
 impl ImageLoader {
-	fn new(name: &str, buffer: Surface) -> Self {
+	fn animated(name: &str, frames: Vec<Surface>, files: Vec<(String, Vec<u8>)>) -> Self {
 		ImageLoader {
 			name: name.to_owned(),
-			data: buffer,
+			frames,
+			files,
+			frame_durations: Vec::new(),
+			beats_per_anim: None,
+			align: Align::default(),
 			fullname: None,
 			source: None,
 			source_other: None,
@@ -71,6 +155,12 @@ impl ImageLoader {
 		self.fullname = data.fullname;
 		self.source = data.source;
 		self.source_other = data.source_other;
+		// Gifs come with their own durations, but the xml takes priority
+		if !data.frame_durations.is_empty() {
+			self.frame_durations = data.frame_durations;
+		}
+		self.beats_per_anim = data.beats_per_anim;
+		self.align = data.align;
 	}
 }
 
@@ -79,8 +169,9 @@ struct ImageData {
 	fullname: Option<String>,
 	source: Option<String>,
 	source_other: Option<String>,
-	// align
-	// frameDuration
+	align: Align,
+	frame_durations: Vec<Duration>,
+	beats_per_anim: Option<f64>,
 }
 
 #[derive(Debug, Default)]
@@ -98,59 +189,107 @@ impl PackInfo {
 			..Default::default()
 		}
 	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn author(&self) -> Option<&str> {
+		self.author.as_ref().map(String::as_ref)
+	}
+
+	pub fn description(&self) -> Option<&str> {
+		self.description.as_ref().map(String::as_ref)
+	}
+
+	pub fn link(&self) -> Option<&str> {
+		self.link.as_ref().map(String::as_ref)
+	}
 }
 
+// Path can be either a zip or a folder
+// Problems are sent back as LoadStatus::Failed - if the whole pack fails it's marked with Skipped::Pack
 pub fn load_respack<T: AsRef<Path>>(path: T, tx: Sender<LoadStatus>) -> Result<()> {
 	let path = path.as_ref();
-	let f = File::open(path)?;
-	let total_size = f.metadata()?.len();
-	tx.send(LoadStatus::TotalSize(total_size))?;
+	match read_respack(path, &tx) {
+		Ok(pack) => tx.send(LoadStatus::Done(pack))?,
+		Err(err) => {
+			let error = LoadError::new(&archive::pack_name(path), None, err.to_string(), Skipped::Pack);
+			tx.send(LoadStatus::Failed(error))?;
+		}
+	}
 
-	let mut archive = ZipArchive::new(f)?;
+	Ok(())
+}
+
+fn read_respack(path: &Path, tx: &Sender<LoadStatus>) -> Result<ResPack> {
+	let mut archive = PackArchive::open(path)?;
+	let total_size = archive.total_size()?;
+	tx.send(LoadStatus::TotalSize(total_size))?;
 
 	let mut images: HashMap<String, ImageLoader> = HashMap::new();
 	let mut audio: HashMap<String, _> = HashMap::new();
 
 	let mut song_data = Vec::new();
 	let mut image_data = Vec::new();
-	let mut pack_info = PackInfo::new(path.file_stem().and_then(OsStr::to_str).unwrap_or("???"));
-
-	let mut loaded_size = 0;
-	for i in 0..archive.len() {
-		let mut file = archive.by_index(i)?;
-		let path: PathBuf = file.name().into();
-
-		let size = file.compressed_size();
-		let name: &str = path.file_stem().and_then(OsStr::to_str).ok_or_else(|| "Bad path")?;
-		match path.extension().and_then(OsStr::to_str) {
-			Some("png") => {
-				let surface = {
-					let mut buffer = Vec::with_capacity(file.size() as usize);
-					file.read_to_end(&mut buffer)?;
+	let mut pack_info = PackInfo::new(&archive::pack_name(path));
 
-					let rwops = RWops::from_bytes(&buffer[..])?;
-					let surface = rwops.load_png()?;
+	let mut errors = Vec::new();
 
-					Surface::from_surface(surface)?
-				};
-
-				let image = ImageLoader::new(name, surface);
-
-				images.insert(name.to_owned(), image);
+	let mut loaded_size = 0;
+	for i in 0..archive.num_files() {
+		// A broken file gets skipped, the rest of the pack carries on
+		let mut file = match archive.by_index(i) {
+			Ok(file) => file,
+			Err(err) => {
+				let error = LoadError::new(&pack_info.name, None, err.to_string(), Skipped::File(format!("file {}", i)));
+				tx.send(LoadStatus::Failed(error))?;
+				continue;
 			}
-			Some("mp3") => {
-				let mut data = Vec::with_capacity(file.size() as usize);
-				file.read_to_end(&mut data)?;
-
-				let decoder = Mp3Decoder::new(Cursor::new(data));
-				let source = (Box::new(decoder) as Box<Source<Item = i16> + Send>).buffered();
-				audio.insert(name.to_owned(), source);
+		};
+		let path: PathBuf = file.path.clone();
+		let file_name = path.to_string_lossy().into_owned();
+
+		let size = file.stored_size;
+		let name: &str = match path.file_stem().and_then(OsStr::to_str) {
+			Some(name) => name,
+			None => {
+				let error = LoadError::new(&pack_info.name, Some(&file_name), "Bad path", Skipped::File(file_name.clone()));
+				tx.send(LoadStatus::Failed(error))?;
+				continue;
 			}
+		};
+		match path.extension().and_then(OsStr::to_str) {
+			Some(extension) if ImageFormat::from_extension(extension).is_some() => match read_file(&mut file) {
+				Ok(buffer) => match image_formats::decode(&buffer, Some(extension)) {
+					Ok(decoded) => {
+						let file = path.file_name().and_then(OsStr::to_str).unwrap_or(name).to_owned();
+						let mut image = ImageLoader::animated(name, decoded.frames, vec![(file, buffer)]);
+						image.frame_durations = decoded.frame_durations;
+						images.insert(name.to_owned(), image);
+					}
+					Err(err) => errors.push(LoadError::new(&pack_info.name, Some(&file_name), err.to_string(), Skipped::Image(name.to_owned()))),
+				},
+				Err(err) => errors.push(LoadError::new(&pack_info.name, Some(&file_name), err.to_string(), Skipped::File(file_name.clone()))),
+			},
+			Some(extension) if AudioFormat::from_extension(extension).is_some() => match read_file(&mut file) {
+				Ok(data) => match EncodedAudio::new(data, Some(extension)) {
+					Ok(source) => {
+						audio.insert(name.to_owned(), source);
+					}
+					Err(err) => errors.push(LoadError::new(&pack_info.name, Some(&file_name), err.to_string(), Skipped::File(file_name.clone()))),
+				},
+				Err(err) => errors.push(LoadError::new(&pack_info.name, Some(&file_name), err.to_string(), Skipped::File(file_name.clone()))),
+			},
 			Some("xml") => {
-				parse_xml(file, &mut song_data, &mut image_data, &mut pack_info);
+				parse_xml(file.reader, &file_name, &mut song_data, &mut image_data, &mut pack_info, &mut errors);
 			}
 			Some("") => {},
-			_ => println!("{:?}", path),
+			_ => eprintln!("{:?}", path),
+		}
+
+		for error in errors.drain(..) {
+			tx.send(LoadStatus::Failed(error))?;
 		}
 		tx.send(LoadStatus::LoadSize(size))?;
 		loaded_size += size;
@@ -160,31 +299,88 @@ pub fn load_respack<T: AsRef<Path>>(path: T, tx: Sender<LoadStatus>) -> Result<(
 	tx.send(LoadStatus::LoadSize(total_size - loaded_size))?;
 
 	// Process songs
-	let songs: Vec<Song> = song_data
-		.into_iter()
-		.filter_map(|data| Song::new(data, &mut audio).ok())
-		.collect();
+	let mut songs = Vec::with_capacity(song_data.len());
+	for data in song_data.into_iter() {
+		let name = data.name.clone();
+		match Song::new(data, &mut audio) {
+			Ok(song) => songs.push(song),
+			Err(err) => {
+				let error = LoadError::new(&pack_info.name, None, err.to_string(), Skipped::Song(name));
+				tx.send(LoadStatus::Failed(error))?;
+			}
+		}
+	}
 
-	if !audio.is_empty() {
-		println!("Warning: Unused audio data {:?}", audio.keys());
+	for name in audio.keys() {
+		let error = LoadError::new(&pack_info.name, None, "Audio not used by any song", Skipped::Unused(name.clone()));
+		tx.send(LoadStatus::Failed(error))?;
 	}
 
 	// Process images
 	for image in image_data.into_iter() {
 		if let Some(loader) = images.get_mut(&image.filename) {
 			loader.add_data(image);
+		} else if let Some(mut loader) = collect_frames(&image.filename, &mut images) {
+			loader.add_data(image);
+			images.insert(loader.name.clone(), loader);
 		} else {
-			println!("Warning: Could not find image {}", image.filename);
+			let error = LoadError::new(&pack_info.name, None, "Could not find image", Skipped::Image(image.filename));
+			tx.send(LoadStatus::Failed(error))?;
 		}
 	}
 
-	tx.send(LoadStatus::Done(ResPack {
+	// Sorted so the same seed always picks the same images
+	let mut images: Vec<ImageLoader> = images.into_values().collect();
+	images.sort_by(|a, b| a.name.cmp(&b.name));
+
+	Ok(ResPack {
+		path: path.to_owned(),
 		info: pack_info,
-		images: images.into_iter().map(|(_k, v)| v).collect(),
+		images,
 		songs,
-	}))?;
+	})
+}
 
-	Ok(())
+fn read_file(file: &mut PackFile) -> io::Result<Vec<u8>> {
+	let mut data = Vec::with_capacity(file.size as usize);
+	file.reader.read_to_end(&mut data)?;
+	Ok(data)
+}
+
+// Animations are stored as name_01.png, name_02.png, ...
+// Pull all the frames for an image out and put them together in order
+fn collect_frames(name: &str, images: &mut HashMap<String, ImageLoader>) -> Option<ImageLoader> {
+	let mut frame_names: Vec<(u32, String)> = images
+		.keys()
+		.filter_map(|key| {
+			if key.len() > name.len() + 1 && key.starts_with(name) && key[name.len()..].starts_with('_') {
+				key[name.len() + 1..].parse::<u32>().ok().map(|num| (num, key.clone()))
+			} else {
+				None
+			}
+		})
+		.collect();
+
+	if frame_names.is_empty() {
+		return None;
+	}
+	frame_names.sort();
+
+	let mut frames = Vec::new();
+	let mut files = Vec::new();
+	for loader in frame_names.into_iter().filter_map(|(_num, key)| images.remove(&key)) {
+		frames.extend(loader.frames);
+		files.extend(loader.files);
+	}
+
+	Some(ImageLoader::animated(name, frames, files))
+}
+
+// Comma separated list of milliseconds
+fn parse_frame_durations(data: &str) -> Option<Vec<Duration>> {
+	data.split(',')
+		.map(|ms| ms.trim().parse::<u64>().ok().map(Duration::from_millis))
+		.collect()
 }
 
 // XML
@@ -212,7 +408,8 @@ enum ImageField {
 	SourceOther,
 	FullName,
 	Align,
-	FrameDuration, // TODO: handle animations
+	FrameDuration,
+	BeatsPerAnim,
 }
 #[derive(Copy, Clone, Debug)]
 enum InfoField {
@@ -222,28 +419,116 @@ enum InfoField {
 	Link,
 }
 
+// Keeps track of where we are in the document so errors can say where they happened
+struct XmlParser<'a, R: Read> {
+	reader: EventReader<BufReader<R>>,
+	elements: Vec<String>,
+
+	pack: &'a str,
+	file: &'a str,
+	errors: &'a mut Vec<LoadError>,
+}
+
+impl<'a, R: Read> XmlParser<'a, R> {
+	fn next(&mut self) -> Option<XmlEvent> {
+		match self.reader.next() {
+			Ok(event) => {
+				match event {
+					XmlEvent::StartElement { ref name, .. } => self.elements.push(name.local_name.clone()),
+					XmlEvent::EndElement { .. } => {
+						self.elements.pop();
+					}
+					_ => {}
+				}
+				Some(event)
+			}
+			Err(err) => {
+				// The reader won't recover from this, so the rest of the file is lost
+				let error = LoadError {
+					pack: self.pack.to_owned(),
+					file: Some(self.file.to_owned()),
+					position: Some(err.position()),
+					element: self.elements.clone(),
+					message: err.msg().to_owned(),
+					skipped: Skipped::File(self.file.to_owned()),
+				};
+				self.errors.push(error);
+				None
+			}
+		}
+	}
+
+	// Read until the element at the given depth (1 is the root) has been closed
+	fn skip_to(&mut self, depth: usize) -> bool {
+		while self.elements.len() >= depth {
+			if self.next().is_none() {
+				return false;
+			}
+		}
+		true
+	}
+
+	// Skip the rest of the innermost open element
+	fn skip_tag(&mut self) -> bool {
+		let depth = self.elements.len();
+		self.skip_to(depth)
+	}
+
+	fn error<T: Into<String>>(&mut self, message: T, skipped: Skipped) {
+		let error = LoadError {
+			pack: self.pack.to_owned(),
+			file: Some(self.file.to_owned()),
+			position: Some(self.reader.position()),
+			element: self.elements.clone(),
+			message: message.into(),
+			skipped,
+		};
+		self.errors.push(error);
+	}
+}
+
 // based off code from stebalien on rust-lang
 // ok this got ugly, clean it up
-fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageData>, pack_info: &mut PackInfo) {
-	let mut reader = EventReader::new(BufReader::new(file));
+// Anything malformed gets reported in `errors` and skipped over rather than stopping the whole pack
+fn parse_xml<R: Read>(
+	file: R,
+	file_name: &str,
+	songs: &mut Vec<SongData>,
+	images: &mut Vec<ImageData>,
+	pack_info: &mut PackInfo,
+	errors: &mut Vec<LoadError>,
+) {
+	let pack_name = pack_info.name.clone();
+	let mut parser = XmlParser {
+		reader: EventReader::new(BufReader::new(file)),
+		elements: Vec::new(),
+
+		pack: &pack_name,
+		file: file_name,
+		errors,
+	};
 
 	let mut state = State::Document;
 
-	let mut song_name = None;
+	let mut song_name = String::new();
 	let mut song_title = None;
 	let mut song_source = None;
 	let mut song_rhythm = Vec::new();
 	let mut song_buildup = None;
 	let mut song_buildup_rhythm = Vec::new();
 
-	let mut image_filename = None;
+	// Depth of the <song> or <image> currently being read, so it can be skipped if it's broken
+	let mut item_depth = 0;
+
+	let mut image_filename = String::new();
 	let mut image_name = None;
 	let mut image_source = None;
 	let mut image_source_other = None;
-	// TODO: handle smart align
-	//let mut image_align = None;
+	let mut image_frame_durations = Vec::new();
+	let mut image_beats_per_anim = None;
+	let mut image_align = None;
 
-	while let Ok(event) = reader.next() {
+	while let Some(event) = parser.next() {
 		state = match state {
 			State::Document => match event {
 				XmlEvent::StartDocument { .. } => State::Document,
@@ -252,14 +537,16 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 					"songs" => State::Songs,
 					"images" => State::Images,
 					_ => {
-						println!("Unknown xml tag {}", name.local_name);
-						xml_skip_tag(&mut reader).unwrap();
+						eprintln!("Unknown xml tag {}", name.local_name);
+						if !parser.skip_tag() {
+							break;
+						}
 						State::Document
 					}
 				},
 				XmlEvent::EndDocument => break,
 				_ => {
-					println!("Unexpected");
+					eprintln!("Unexpected");
 					State::Document
 				}
 			},
@@ -268,26 +555,27 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 					name, attributes, ..
 				} => {
 					if name.local_name != "song" {
-						panic!("Expected a song tag - got {}", name.local_name);
-					}
-
-					for attr in attributes.into_iter() {
-						if attr.name.local_name == "name" {
-							song_name = Some(attr.value);
+						parser.error(format!("Expected a song tag - got {}", name.local_name), Skipped::Element(name.local_name));
+						if !parser.skip_tag() {
 							break;
 						}
+						State::Songs
+					} else if let Some(attr) = attributes.into_iter().find(|attr| attr.name.local_name == "name") {
+						song_name = attr.value;
+						item_depth = parser.elements.len();
+						State::Song(None)
+					} else {
+						parser.error("Expected a song name", Skipped::Element(name.local_name));
+						if !parser.skip_tag() {
+							break;
+						}
+						State::Songs
 					}
-
-					if song_name.is_none() {
-						panic!("Expected a song name");
-					}
-
-					State::Song(None)
 				}
 				XmlEvent::EndElement { .. } => State::Document,
 				XmlEvent::Whitespace(_) => State::Songs,
 				_ => {
-					println!("Expected a song tag - got {:?}", event);
+					eprintln!("Expected a song tag - got {:?}", event);
 					State::Songs
 				}
 			},
@@ -299,82 +587,133 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 					"buildup" => State::Song(Some(SongField::Buildup)),
 					"buildupRhythm" => State::Song(Some(SongField::BuildupRhythm)),
 					_ => {
-						println!("Unknown song field {}", name.local_name);
-						xml_skip_tag(&mut reader).unwrap();
+						eprintln!("Unknown song field {}", name.local_name);
+						if !parser.skip_tag() {
+							break;
+						}
 						State::Song(None)
 					}
 				},
 				XmlEvent::EndElement { .. } => {
-					if song_rhythm.is_empty() {
-						// TODO: be graceful
-						panic!("Empty rhythm");
+					let name = std::mem::take(&mut song_name);
+					let title = song_title.take();
+					let source = song_source.take();
+					let rhythm = std::mem::take(&mut song_rhythm);
+					let buildup = song_buildup.take();
+					let buildup_rhythm = std::mem::take(&mut song_buildup_rhythm);
+
+					if rhythm.is_empty() {
+						parser.error("Empty rhythm", Skipped::Song(name));
+					} else {
+						songs.push(SongData {
+							// Fall back on the file name if there's no title
+							title: title.unwrap_or_else(|| name.clone()),
+							name,
+							source,
+							rhythm,
+							buildup,
+							buildup_rhythm,
+						});
 					}
-
-					let song = SongData {
-						name: song_name.take().unwrap(),
-						title: song_title.take().unwrap(),
-						source: song_source.take(),
-						rhythm: std::mem::replace(&mut song_rhythm, Vec::new()),
-						buildup: song_buildup.take(),
-						buildup_rhythm: std::mem::replace(&mut song_buildup_rhythm, Vec::new()),
-					};
-
-					songs.push(song);
 					State::Songs
 				}
 				_ => State::Song(None),
 			},
 			State::Song(Some(field)) => match event {
 				XmlEvent::Characters(data) => {
-					match field {
-						SongField::Title => song_title = Some(data),
-						SongField::Source => song_source = Some(data),
+					let rhythm_error = match field {
+						SongField::Title => {
+							song_title = Some(data);
+							None
+						}
+						SongField::Source => {
+							song_source = Some(data);
+							None
+						}
 						SongField::Rhythm => {
-							if !data.is_ascii() {
-								panic!("Expected ascii characters in rhythm");
-							}
 							song_rhythm = data.chars().collect();
+							None
+						}
+						SongField::Buildup => {
+							song_buildup = Some(data);
+							None
 						}
-						SongField::Buildup => song_buildup = Some(data),
 						SongField::BuildupRhythm => {
-							if !data.is_ascii() {
-								panic!("Expected ascii characters in rhythm");
-							}
 							if data.is_empty() {
-								panic!("Buildup rhythm empty!");
+								Some("Buildup rhythm empty!")
+							} else {
+								song_buildup_rhythm = data.chars().collect();
+								None
 							}
-							song_buildup_rhythm = data.chars().collect();
 						}
+					};
+
+					if let Some(message) = rhythm_error {
+						// Drop the whole song
+						let name = std::mem::take(&mut song_name);
+						parser.error(message, Skipped::Song(name));
+
+						if !parser.skip_to(item_depth) {
+							break;
+						}
+
+						song_title = None;
+						song_source = None;
+						song_rhythm.clear();
+						song_buildup = None;
+						song_buildup_rhythm.clear();
+
+						State::Songs
+					} else {
+						State::Song(Some(field))
 					}
-					State::Song(Some(field))
 				}
 				XmlEvent::EndElement { .. } => State::Song(None),
-				_ => panic!("Expected data for tag {:?}", field),
+				XmlEvent::StartElement { .. } => {
+					let name = std::mem::take(&mut song_name);
+					parser.error(format!("Expected data for tag {:?}", field), Skipped::Song(name));
+					if !parser.skip_to(item_depth) {
+						break;
+					}
+
+					song_title = None;
+					song_source = None;
+					song_rhythm.clear();
+					song_buildup = None;
+					song_buildup_rhythm.clear();
+
+					State::Songs
+				}
+				_ => State::Song(Some(field)),
 			},
 			State::Images => match event {
 				XmlEvent::StartElement {
 					name, attributes, ..
 				} => {
 					if name.local_name != "image" {
-						panic!("Expected an image tag - got {}", name.local_name);
-					}
-
-					for attr in attributes.into_iter() {
-						if attr.name.local_name == "name" {
-							image_filename = Some(attr.value);
+						parser.error(format!("Expected an image tag - got {}", name.local_name), Skipped::Element(name.local_name));
+						if !parser.skip_tag() {
 							break;
 						}
+						State::Images
+					} else if let Some(attr) = attributes.into_iter().find(|attr| attr.name.local_name == "name") {
+						image_filename = attr.value;
+						item_depth = parser.elements.len();
+						State::Image(None)
+					} else {
+						parser.error("Expected an image name", Skipped::Element(name.local_name));
+						if !parser.skip_tag() {
+							break;
+						}
+						State::Images
 					}
-
-					if image_filename.is_none() {
-						panic!("Expected an image name");
-					}
-
-					State::Image(None)
 				}
 				XmlEvent::EndElement { .. } => State::Document,
 				XmlEvent::Whitespace(_) => State::Images,
-				_ => panic!("Expected an image tag - got {:?}", event),
+				_ => {
+					eprintln!("Expected an image tag - got {:?}", event);
+					State::Images
+				}
 			},
 			State::Image(None) => match event {
 				XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
@@ -383,18 +722,24 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 					"fullname" => State::Image(Some(ImageField::FullName)),
 					"align" => State::Image(Some(ImageField::Align)),
 					"frameDuration" => State::Image(Some(ImageField::FrameDuration)),
+					"beatsPerAnim" => State::Image(Some(ImageField::BeatsPerAnim)),
 					_ => {
-						println!("Unknown image field {}", name.local_name);
-						xml_skip_tag(&mut reader).unwrap();
+						eprintln!("Unknown image field {}", name.local_name);
+						if !parser.skip_tag() {
+							break;
+						}
 						State::Image(None)
 					}
 				},
 				XmlEvent::EndElement { .. } => {
 					let image = ImageData {
-						filename: image_filename.take().unwrap(),
+						filename: std::mem::take(&mut image_filename),
 						fullname: image_name.take(),
 						source: image_source.take(),
 						source_other: image_source_other.take(),
+						frame_durations: std::mem::take(&mut image_frame_durations),
+						beats_per_anim: image_beats_per_anim.take(),
+						align: image_align.take().unwrap_or_default(),
 					};
 
 					images.push(image);
@@ -408,13 +753,39 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 						ImageField::Source => image_source = Some(data),
 						ImageField::SourceOther => image_source_other = Some(data),
 						ImageField::FullName => image_name = Some(data),
-						ImageField::Align => {}
-						ImageField::FrameDuration => {}
+						ImageField::Align => match Align::from_str(&data) {
+							Some(align) => image_align = Some(align),
+							None => parser.error(format!("Unknown alignment {}", data), Skipped::Element("align".to_owned())),
+						},
+						ImageField::FrameDuration => match parse_frame_durations(&data) {
+							Some(durations) => image_frame_durations = durations,
+							None => parser.error("Expected a list of frame durations", Skipped::Element("frameDuration".to_owned())),
+						},
+						ImageField::BeatsPerAnim => match data.trim().parse::<f64>() {
+							Ok(beats) if beats > 0.0 => image_beats_per_anim = Some(beats),
+							_ => parser.error("Expected a positive number of beats", Skipped::Element("beatsPerAnim".to_owned())),
+						},
 					}
 					State::Image(Some(field))
 				}
 				XmlEvent::EndElement { .. } => State::Image(None),
-				_ => panic!("Expected data for tag {:?}", field),
+				XmlEvent::StartElement { .. } => {
+					let name = std::mem::take(&mut image_filename);
+					parser.error(format!("Expected data for tag {:?}", field), Skipped::Image(name));
+					if !parser.skip_to(item_depth) {
+						break;
+					}
+
+					image_name = None;
+					image_source = None;
+					image_source_other = None;
+					image_frame_durations.clear();
+					image_beats_per_anim = None;
+					image_align = None;
+
+					State::Images
+				}
+				_ => State::Image(Some(field)),
 			},
 			State::Info(None) => match event {
 				XmlEvent::StartElement { ref name, .. } => match name.local_name.as_ref() {
@@ -423,8 +794,10 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 					"description" => State::Info(Some(InfoField::Description)),
 					"link" => State::Info(Some(InfoField::Link)),
 					_ => {
-						println!("Unknown info field {}", name.local_name);
-						xml_skip_tag(&mut reader).unwrap();
+						eprintln!("Unknown info field {}", name.local_name);
+						if !parser.skip_tag() {
+							break;
+						}
 						State::Info(None)
 					}
 				},
@@ -443,23 +816,10 @@ fn parse_xml(file: ZipFile, songs: &mut Vec<SongData>, images: &mut Vec<ImageDat
 				}
 				XmlEvent::EndElement { .. } => State::Info(None),
 				_ => {
-					println!("Expected data for tag {:?}", field);
+					eprintln!("Expected data for tag {:?}", field);
 					State::Info(Some(field))
 				}
 			}
 		}
 	}
 }
-
-fn xml_skip_tag<R: Read>(reader: &mut EventReader<R>) -> Result<()> {
-	let mut depth = 1;
-	while depth > 0 {
-		match reader.next() {
-			Ok(XmlEvent::StartElement { .. }) => depth += 1,
-			Ok(XmlEvent::EndElement { .. }) => depth -= 1,
-			Ok(_event) => {}
-			_ => return Err("Unexpected event error".into()),
-		}
-	}
-	Ok(())
-}
@@ -1,61 +1,161 @@
 #[macro_use]
 extern crate serde_derive;
-extern crate glob;
 extern crate rand;
 extern crate rodio;
 extern crate sdl2;
 extern crate zip;
 extern crate toml;
 
-use std::thread;
-use std::sync::mpsc::channel;
-use std::fs::File;
-use std::io::Read;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
 use std::time::{Duration, Instant};
-use std::ffi::OsStr;
 
 use sdl2::pixels::Color as Colour;
 use sdl2::event::Event;
 use sdl2::keyboard::Scancode;
-use sdl2::render::BlendMode;
+use sdl2::mouse::MouseButton;
+use sdl2::render::{BlendMode, Canvas};
+use sdl2::video::{FullscreenType, Window};
 
 use rodio::source::{Buffered, Source};
-use rodio::Sample;
 
-use glob::glob;
+use rand::SeedableRng;
 
 mod mp3;
+mod audio;
+mod image_formats;
+mod archive;
 mod loader;
 mod ui;
+mod glyphs;
+mod layouts;
 mod surface;
 mod images;
 mod songs;
+mod beats;
 mod screen;
-
-use loader::LoadStatus;
-use ui::TextUi;
+mod clock;
+mod render;
+mod cli;
+// serde_derive 1.0 puts the Deserialize impl inside a const, which newer compilers warn about
+#[allow(non_local_definitions)]
+mod config;
+mod keymap;
+mod packs;
+mod check;
+mod browser;
+mod credits;
+mod editor;
+mod writer;
+mod repack;
+
+use loader::{LoadStatus, ResPack, Skipped};
+use ui::{HelpOverlay, TextUi};
+use glyphs::GlyphAtlas;
 use ui::UiLayout;
+use layouts::Layout;
 use images::ImageManager;
 use songs::SongManager;
 use screen::Screen;
-
-type Error = Box<std::error::Error>;
+use cli::{Args, Command};
+use config::Config;
+use packs::Packs;
+use browser::{Browser, BrowserKind};
+use credits::CreditsPanel;
+use editor::Editor;
+use keymap::{Action, Keymap};
+
+type Error = Box<dyn std::error::Error>;
 type Result<T> = std::result::Result<T, Error>;
 
 //type AudioData = Buffered<Box<Source<Item = i16> + Send>>;
-type AudioData = Buffered<Box<Source<Item = i16> + Send>>;
+type AudioData = Buffered<Box<dyn Source<Item = i16> + Send>>;
+
+const VOLUME_STEP: f32 = 0.1;
+
+// All the random colours, images and songs come from one of these
+// so the same seed gives the same run
+type Random = rand::Isaac64Rng;
+
+// Picks a seed if there isn't one, and says what it is so the run can be repeated
+fn new_rng(seed: Option<u64>) -> Random {
+	let seed = seed.unwrap_or_else(rand::random);
+	eprintln!("Seed: {}", seed);
+	Random::from_seed(&[seed][..])
+}
 
 fn main() {
+	let args: Vec<String> = std::env::args().skip(1).collect();
+	let args = match Args::parse(&args) {
+		Ok(args) => args,
+		Err(err) => {
+			eprintln!("{}\n\n{}", err, cli::USAGE);
+			std::process::exit(1);
+		}
+	};
+
+	// Config
+	let config_path = args.config.clone().unwrap_or_else(|| PathBuf::from(config::DEFAULT_CONFIG));
+	let mut config = Config::load(&config_path).unwrap_or_else(|err| {
+		// Only complain about a missing file if it was asked for
+		if args.config.is_some() || config_path.exists() {
+			eprintln!("Error loading config {}: {}", config_path.display(), err);
+		}
+		Config::default()
+	});
+	config.merge(&args);
+
+	match args.command {
+		Command::Play => {}
+		Command::Help => {
+			println!("{}", cli::USAGE);
+			return;
+		}
+		Command::Keys => {
+			print!("{}", Keymap::new(config.keys.as_ref()).to_config());
+			return;
+		}
+		Command::Check => match check::run(&config) {
+			Ok(false) => return,
+			Ok(true) => std::process::exit(1),
+			Err(err) => {
+				eprintln!("Error checking: {}", err);
+				std::process::exit(2);
+			}
+		},
+		Command::Repack(ref output) => {
+			if let Err(err) = repack::run(&config, output) {
+				eprintln!("Error repacking: {}", err);
+				std::process::exit(1);
+			}
+			return;
+		}
+		Command::Render(ref options) => {
+			if let Err(err) = render::run(options, &config) {
+				eprintln!("Error rendering: {}", err);
+				std::process::exit(1);
+			}
+			return;
+		}
+	}
+
+	// Each pack only loads once, however many times it's asked for
+	let mut respacks = config.respack_paths();
+	let mut seen = HashSet::new();
+	respacks.retain(|path| seen.insert(path.clone()));
+
 	let sdl_context = sdl2::init().unwrap();
 	let video_subsystem = sdl_context.video().unwrap();
 	let _audio_subsystem = sdl_context.audio().unwrap();
 
-	let window = video_subsystem
-		.window("0x40-hues.rs", 1280, 720)
-		.position_centered()
-		.build()
-		.unwrap();
+	let (width, height) = config.window_size();
+	let mut window = video_subsystem.window("0x40-hues.rs", width, height);
+	window.position_centered().resizable();
+	if config.fullscreen == Some(true) {
+		window.fullscreen_desktop();
+	}
+	let window = window.build().unwrap();
 
 	sdl2::hint::set("SDL_RENDER_VSYNC", "1");
 
@@ -66,11 +166,11 @@ fn main() {
 	let ttf_context = sdl2::ttf::init().expect("Could not init ttf");
 
 	// Image
-	sdl2::image::init(sdl2::image::INIT_PNG).expect("Could not init png");
+	sdl2::image::init(sdl2::image::INIT_PNG | sdl2::image::INIT_JPG | sdl2::image::INIT_WEBP).expect("Could not init sdl image");
 
 	// Font
 	let font = ttf_context
-		.load_font("respacks/PetMe64.ttf", 12)
+		.load_font(config.font(), 12)
 		.expect("Could not load font");
 
 	// Events
@@ -84,85 +184,82 @@ fn main() {
 	let mut image_manager = ImageManager::new(&texture_creator);
 	let mut song_manager = SongManager::new();
 
-	// Config
-	let config: Option<Config> = File::open("config.toml").map_err(Error::from).and_then(|mut config_file| {
-		let mut config_string = String::new();
-		config_file.read_to_string(&mut config_string)?;
-		Ok(config_string)
-	}).and_then(|cfg| toml::from_str::<Config>(&cfg).map_err(|err| format!("	{}", err).into())).ok();
-
 	// Load resources
-	// this feels kinda hacky - maybe I rewrite later
-	let respacks = if let Some(Config { respacks: Some(ref packs), .. }) = config {
-		packs.clone()
-	} else {
-		glob("respacks/*.zip")
-			.expect("Could not do this")
-			.filter_map(std::result::Result::ok)
-			.filter_map(|path| path.file_stem().and_then(OsStr::to_str).map(str::to_owned))	// and_then is a flatmap
-			.collect::<Vec<String>>()
-	};
-
 	let mut remaining_packs = respacks.len();
 
-	let (tx, rx) = channel();
-	for packname in respacks.iter() {
-		let tx = tx.clone();
-		let path = format!("respacks/{}.zip", packname);
-		thread::spawn(move || loader::load_respack(path, tx).map_err(|err| println!("Error loading pack: {}", err)));
-		println!("Loading {}", packname);
+	let mut packs = Packs::new();
+	for path in &respacks {
+		packs.load(path.clone());
 	}
+	// Packs finish in any order, they get added in the order they were asked for
+	let mut loaded_packs = Vec::new();
 
 	// Draw loading screen
-	let mut load_text = TextUi::create("Loading...", &font, &texture_creator).unwrap();
-	load_text.centre(0, 0, 1280, 720);
+	let mut load_atlas = GlyphAtlas::new(&font, &texture_creator).unwrap();
+	let mut load_text = TextUi::create("Loading...", &mut load_atlas).unwrap();
 	let (mut loaded_size, mut total_size): (u64, u64) = (0, 0);
-	'loading: loop {
+	'loading: while remaining_packs > 0 {
 		for event in event_pump.poll_iter() {
 			if let Event::Quit { .. } = event {
 				return;
 			}
 		}
 		// Update loading
-		let val = rx.try_recv();
+		let val = packs.try_recv();
 		let mut changed = false;
 		match val {
-			Ok(LoadStatus::TotalSize(size)) => {
+			Some(LoadStatus::TotalSize(size)) => {
 				total_size += size;
 				changed = true;
 			}
-			Ok(LoadStatus::LoadSize(size)) => {
+			Some(LoadStatus::LoadSize(size)) => {
 				loaded_size += size;
 				changed = true;
 			}
-			Ok(LoadStatus::Done(pack)) => {
-				println!("Pack loaded: {:?}", pack.info);
-				image_manager.extend(pack.images);
-				song_manager.extend(pack.songs);
+			Some(LoadStatus::Failed(err)) => {
+				println!("Error loading pack: {}", err);
+
+				if err.skipped == Skipped::Pack {
+					remaining_packs -= 1;
+					if remaining_packs == 0 {
+						break 'loading;
+					}
+				}
+			}
+			Some(LoadStatus::Done(pack)) => {
+				loaded_packs.push(pack);
 
 				remaining_packs -= 1;
 				if remaining_packs == 0 {
 					break 'loading;
 				}
 			}
-			Err(_) => {}
+			None => {}
 		}
 
 		// Rerender text if changed
 		if changed {
 			let text = format!("Loading {}/{}", loaded_size, total_size);
-			load_text = TextUi::create(text, &font, &texture_creator).unwrap();
-			load_text.centre(0, 0, 1280, 720);
+			load_text.set_text(text, &mut load_atlas).unwrap();
 		}
 
 		// Render
 		canvas.clear();
 
-		load_text.draw(&mut canvas).unwrap();
+		// Window might have been resized
+		let (width, height) = canvas.output_size().unwrap();
+		load_text.centre(0, 0, width, height);
+
+		load_text.draw(&mut canvas, &mut load_atlas).unwrap();
 
 		canvas.present();
 	}
 
+	loaded_packs.sort_by_key(|pack: &ResPack| respacks.iter().position(|path| *path == pack.path));
+	for pack in loaded_packs {
+		add_pack(pack, &mut packs, &mut image_manager, &mut song_manager);
+	}
+
 	//
 	let mut screen = Screen::new(&texture_creator);
 	screen.clear(&mut canvas);
@@ -170,14 +267,52 @@ fn main() {
 	let mut frame_timer = Instant::now();
 	let mut num_frames = 0;
 
-	let mut basic_ui = ui::BasicUi::new(&font, &texture_creator);
+	let mut ui = Layout::new(config.layout(), &font, &texture_creator);
+	let mut rng = new_rng(config.seed);
 
-	image_manager.random_image(&mut basic_ui);
+	let keymap = Keymap::new(config.keys.as_ref());
+	let mut help = HelpOverlay::new(&keymap, &font, &texture_creator).unwrap();
+	let mut show_ui = true;
+	let mut show_help = false;
+	let mut browser: Option<Browser<_>> = None;
+	let mut credits = CreditsPanel::new(&font, &texture_creator).unwrap();
+	let mut show_credits = false;
+	let mut editor: Option<Editor<_>> = None;
 
-	match config {
-		Some(Config { song: Some(song), .. }) => song_manager.play_song(song, &mut basic_ui).ok(),
+	// Typing only goes to the browser or editor, and only once one's open
+	let text_input = video_subsystem.text_input();
+	text_input.stop();
+
+	if let Some(latency) = config.latency {
+		song_manager.set_latency(latency);
+	}
+	if let Some(volume) = config.volume {
+		song_manager.set_volume(volume);
+	}
+	if let Some(muted) = config.muted {
+		song_manager.set_muted(muted);
+	}
+	if let Some(fade) = config.fade {
+		song_manager.set_fade(Duration::from_millis(fade));
+	}
+	if let Some(blackout) = config.blackout_on_change {
+		song_manager.set_blackout_on_change(blackout);
+	}
+	song_manager.set_buildup_mode(config.buildup_mode());
+
+	image_manager.random_image(&mut rng, &mut ui);
+	if let Some(ref image) = config.image {
+		image_manager.show_image(image, &mut ui).unwrap_or_else(|err| eprintln!("{}: {}", err, image));
+	}
+	image_manager.set_full_auto(config.full_auto.unwrap_or(true), &mut ui);
+
+	match config.song {
+		Some(ref song) => song_manager.play_song(song, &mut ui).ok(),
 		_ => None
-	}.unwrap_or_else(|| song_manager.play_random(&mut basic_ui));
+	}.unwrap_or_else(|| song_manager.play_random(&mut rng, &mut ui));
+
+	// Written back to the config on quit if they changed
+	let start_volume = (song_manager.volume(), song_manager.muted());
 
 	'running: loop {
 		for event in event_pump.poll_iter() {
@@ -185,33 +320,207 @@ fn main() {
 				Event::Quit { .. } => {
 					break 'running;
 				}
-				Event::KeyDown { scancode, .. } => match scancode {
-					Some(Scancode::F) => image_manager.toggle_full_auto(&mut basic_ui),
-					Some(Scancode::J) => song_manager.prev_song(&mut basic_ui),
-					Some(Scancode::K) => song_manager.next_song(&mut basic_ui),
-					Some(Scancode::N) => image_manager.prev_image(&mut basic_ui),
-					Some(Scancode::M) => image_manager.next_image(&mut basic_ui),
-					_ => {}
-				},
+				Event::MouseButtonDown { mouse_btn: MouseButton::Left, x, y, .. } if show_credits => {
+					if let Some(link) = credits.link_at(x, y, canvas.output_size().unwrap()) {
+						match video_subsystem.clipboard().set_clipboard_text(link) {
+							Ok(()) => println!("Copied {}", link),
+							Err(err) => eprintln!("Could not copy {}: {}", link, err),
+						}
+					}
+				}
+				Event::TextInput { ref text, .. } => {
+					if let Some(ref mut browser) = browser {
+						browser.type_text(text);
+					}
+					if let Some(ref mut editor) = editor {
+						editor.type_text(text, &mut song_manager, &mut ui);
+					}
+				}
+				// The browser has the keyboard while it's open
+				Event::KeyDown { scancode: Some(scancode), .. } if browser.is_some() => {
+					let mut close = false;
+					if let Some(ref mut browser) = browser {
+						let page = browser.page_size();
+						match scancode {
+							Scancode::Escape => close = true,
+							Scancode::Return | Scancode::KpEnter => {
+								match (browser.kind(), browser.selected()) {
+									(BrowserKind::Songs, Some(index)) => song_manager.play_index(index, &mut ui),
+									(BrowserKind::Images, Some(index)) => image_manager.lock_image(index, &mut ui),
+									(_, None) => {}
+								}
+								close = true;
+							}
+							Scancode::Up => browser.move_selection(-1),
+							Scancode::Down => browser.move_selection(1),
+							Scancode::PageUp => browser.move_selection(-page),
+							Scancode::PageDown => browser.move_selection(page),
+							Scancode::Backspace => browser.backspace(),
+							_ => {}
+						}
+					}
+					if close {
+						browser = None;
+						text_input.stop();
+					}
+				}
+				// So does the editor, apart from pausing
+				Event::KeyDown { scancode: Some(scancode), repeat, .. } if editor.is_some() => {
+					let mut close = false;
+					if let Some(ref mut editor) = editor {
+						match scancode {
+							Scancode::Escape => close = true,
+							Scancode::Left => editor.move_cursor(-1),
+							Scancode::Right => editor.move_cursor(1),
+							Scancode::Up => editor.show_buildup(true),
+							Scancode::Down => editor.show_buildup(false),
+							Scancode::Backspace => editor.backspace(&mut song_manager, &mut ui),
+							Scancode::Delete => editor.delete(&mut song_manager, &mut ui),
+							_ if repeat => {}
+							Scancode::Insert => editor.toggle_insert(),
+							Scancode::PageUp => editor.double_resolution(&mut song_manager, &mut ui),
+							Scancode::PageDown => editor.halve_resolution(&mut song_manager, &mut ui),
+							Scancode::Return | Scancode::KpEnter => editor.tap(&mut song_manager, &mut ui),
+							Scancode::Home => editor.play_from_cursor(&mut song_manager, &mut ui),
+							Scancode::Space => song_manager.toggle_pause(),
+							Scancode::F2 => editor.save(),
+							_ => {}
+						}
+					}
+					if close {
+						close_editor(&mut editor, &mut song_manager, &mut ui);
+						text_input.stop();
+					}
+				}
+				Event::KeyDown { scancode: Some(scancode), repeat, .. } => {
+					let action = match keymap.action(scancode) {
+						Some(action) => action,
+						None => continue,
+					};
+					// Only volume and scrubbing make sense to hold down
+					let holdable = matches!(action, Action::VolumeUp | Action::VolumeDown | Action::PrevBeat | Action::NextBeat);
+					if repeat && !holdable {
+						continue;
+					}
+					match action {
+						Action::ToggleFullAuto => image_manager.toggle_full_auto(&mut ui),
+						Action::PrevSong => song_manager.prev_song(&mut ui),
+						Action::NextSong => song_manager.next_song(&mut ui),
+						Action::RandomSong => song_manager.play_random(&mut rng, &mut ui),
+						Action::PrevImage => image_manager.prev_image(&mut ui),
+						Action::NextImage => image_manager.next_image(&mut ui),
+						Action::LockImage => image_manager.toggle_lock(),
+						Action::Pause => song_manager.toggle_pause(),
+						Action::PrevBeat => song_manager.prev_beat(&mut ui),
+						Action::NextBeat => song_manager.next_beat(&mut ui),
+						Action::PrevLoop => song_manager.prev_loop(&mut ui),
+						Action::NextLoop => song_manager.next_loop(&mut ui),
+						Action::RestartBuildup => song_manager.restart_buildup(&mut ui),
+						Action::VolumeDown => song_manager.change_volume(-VOLUME_STEP),
+						Action::VolumeUp => song_manager.change_volume(VOLUME_STEP),
+						Action::Mute => song_manager.toggle_mute(),
+						Action::ToggleBlur => image_manager.toggle_blur(),
+						Action::ToggleUi => show_ui = !show_ui,
+						Action::NextLayout => {
+							ui = Layout::new(ui.kind().next(), &font, &texture_creator);
+							screen.update_ui(&mut ui);
+							image_manager.update_ui(&mut ui);
+							song_manager.update_ui(&mut ui);
+						}
+						Action::UnloadPack => {
+							if let Some(path) = song_manager.current_pack().map(Path::to_owned) {
+								packs.unload(&path);
+								song_manager.remove_pack(&path, &mut rng, &mut ui);
+								image_manager.remove_pack(&path, &mut rng, &mut ui);
+							}
+						}
+						Action::ReloadPacks => packs.reload(),
+						Action::SongBrowser => {
+							browser = browser::song_browser(&song_manager, &packs, &font, &texture_creator).ok();
+							text_input.start();
+						}
+						Action::ImageBrowser => {
+							browser = browser::image_browser(&image_manager, &font, &texture_creator).ok();
+							text_input.start();
+						}
+						Action::Editor => match Editor::new(&song_manager, &font, &texture_creator) {
+							Ok(new_editor) => {
+								editor = Some(new_editor);
+								text_input.start();
+							}
+							Err(err) => eprintln!("Could not open the editor: {}", err),
+						},
+						Action::ToggleFullscreen => toggle_fullscreen(&mut canvas),
+						Action::Credits => show_credits = !show_credits,
+						Action::Help => show_help = !show_help,
+						Action::Quit => break 'running,
+					}
+				}
+				_ => {}
+			}
+		}
+
+		// Packs coming and going while it plays
+		while let Some(status) = packs.try_recv() {
+			match status {
+				LoadStatus::Done(pack) => {
+					if !packs.accept(&pack.path) {
+						continue;
+					}
+					add_pack(pack, &mut packs, &mut image_manager, &mut song_manager);
+					if !song_manager.is_playing() {
+						song_manager.play_random(&mut rng, &mut ui);
+					}
+				}
+				LoadStatus::Failed(err) => println!("Error loading pack: {}", err),
 				_ => {}
 			}
 		}
+		for path in packs.update() {
+			song_manager.remove_pack(&path, &mut rng, &mut ui);
+			image_manager.remove_pack(&path, &mut rng, &mut ui);
+			// Everything after it moves down, so the indices in the list are wrong now
+			browser = None;
+		}
+		if editor.as_ref().is_some_and(|editor| !editor.is_editing(song_manager.current_song())) {
+			close_editor(&mut editor, &mut song_manager, &mut ui);
+			text_input.stop();
+		}
 
-		song_manager.update_beat(&mut screen, &mut image_manager, &mut basic_ui);
+		song_manager.update_beat(&mut screen, &mut image_manager, &mut rng, &mut ui);
 
 		// Clear screen with colour
 		screen.clear(&mut canvas);
 
 		// Draw image
-		image_manager.draw_image(&mut canvas, &mut basic_ui);
+		image_manager.draw_image(&mut canvas, &mut ui);
 
 		// Text
-		basic_ui.draw(&mut canvas).unwrap();
+		if show_ui {
+			ui.draw(&mut canvas).unwrap();
+		}
 
 		// Overlay blackout
 		screen.draw(&mut canvas); // maybe make screen draw the image and ui too
 							// maybe make the screen hold the canvas
 
+		if show_credits {
+			let image = image_manager.current_image().map(|image| (image, packs.info(image.pack())));
+			let song = song_manager.current_song().map(|song| (song, packs.info(song.pack())));
+			credits.update(image, song).unwrap();
+			credits.draw(&mut canvas).unwrap();
+		}
+
+		if show_help {
+			help.draw(&mut canvas).unwrap();
+		}
+		if let Some(ref mut browser) = browser {
+			browser.draw(&mut canvas).unwrap();
+		}
+		if let Some(ref mut editor) = editor {
+			editor.draw(&mut canvas, song_manager.current_beat()).unwrap();
+		}
+
 		canvas.present();
 
 		// Track fps
@@ -225,12 +534,42 @@ fn main() {
 			num_frames = 0;
 		}
 	}
+
+	let (volume, muted) = (song_manager.volume(), song_manager.muted());
+	if (volume, muted) != start_volume {
+		Config::save_volume(&config_path, volume, muted)
+			.unwrap_or_else(|err| eprintln!("Could not save volume to {}: {}", config_path.display(), err));
+	}
+}
+
+// Hands a loaded pack over to everything that needs a bit of it
+fn add_pack<T>(pack: ResPack, packs: &mut Packs, image_manager: &mut ImageManager<T>, song_manager: &mut SongManager) {
+	println!("Pack loaded: {:?}", pack.info);
+	image_manager.extend(&pack.path, pack.images);
+	song_manager.extend(&pack.path, pack.songs);
+	packs.set_info(&pack.path, pack.info);
+}
+
+// Unsaved edits get thrown away, the song goes back to how it was
+fn close_editor<T, S: UiLayout>(editor: &mut Option<Editor<T>>, song_manager: &mut SongManager, ui: &mut S) {
+	if let Some(editor) = editor.take() {
+		if editor.is_unsaved() {
+			println!("Discarded unsaved changes to {}", editor.title());
+		}
+		editor.close(song_manager, ui);
+	}
 }
 
-#[derive(Deserialize, Debug)]
-struct Config {
-	respacks: Option<Vec<String>>,
-	song: Option<String>
+// Everything gets drawn to fit canvas.output_size() each frame, so resizing needs nothing else
+fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+	let window = canvas.window_mut();
+	let fullscreen = match window.fullscreen_state() {
+		FullscreenType::Off => FullscreenType::Desktop,
+		_ => FullscreenType::Off,
+	};
+	window
+		.set_fullscreen(fullscreen)
+		.unwrap_or_else(|err| eprintln!("Could not toggle fullscreen: {}", err));
 }
 
 fn _duration_to_millis(d: Duration) -> f64 {
@@ -5,6 +5,8 @@ use std::time::Duration;
 
 use rodio::Source;
 
+use Result;
+
 unsafe impl<R: Read + Send> Send for Mp3Decoder<R> {}
 
 pub struct Mp3Decoder<R>
@@ -15,42 +17,166 @@ where
 	current_frame: simplemad::Frame,
 	current_frame_channel: usize,
 	current_frame_sample_pos: usize,
-
-	duration: Duration,
 }
 
 impl<R> Mp3Decoder<R>
 where
-	R: Read + Seek + Clone,
+	R: Read + Seek,
 {
-	pub fn new(mut data: R) -> Mp3Decoder<R> {
+	// Doesn't know how long it is, that's worked out once with duration() instead
+	pub fn new(mut data: R) -> Result<Mp3Decoder<R>> {
 		if !is_mp3(data.by_ref()) {
-			panic!("Not valid mp3.");
+			return Err("Not valid mp3.".into());
 		}
 
-		let duration = {
-			let headers = simplemad::Decoder::decode_headers(data.clone()).unwrap();
-			headers
-				.filter_map(|r| match r {
-					Ok(f) => Some(f.duration),
-					Err(_) => None,
-				})
-				.fold(Duration::new(0, 0), |acc, dtn| acc + dtn)
-		};
-
-		let mut reader = simplemad::Decoder::decode(data).unwrap();
+		let mut reader = simplemad::Decoder::decode(data).map_err(|err| format!("{:?}", err))?;
 
 		let current_frame = next_frame(&mut reader);
 
-		Mp3Decoder {
-			reader: reader,
-			current_frame: current_frame,
+		Ok(Mp3Decoder {
+			reader,
+			current_frame,
 			current_frame_channel: 0,
 			current_frame_sample_pos: 0,
+		})
+	}
+}
+
+// Bitrates in kbps by bitrate index, 0 is free format and 15 is invalid
+const BITRATES_V1_L1: [u32; 15] = [0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448];
+const BITRATES_V1_L2: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384];
+const BITRATES_V1_L3: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const BITRATES_V2_L1: [u32; 15] = [0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256];
+const BITRATES_V2_L23: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+// What the first frame header says about the rest of the file
+struct FrameHeader {
+	mpeg1: bool,
+	layer: u8,
+	mono: bool,
+	bitrate: u32, // kbps
+	samples_rate: u32,
+}
+
+impl FrameHeader {
+	fn parse(bytes: &[u8]) -> Option<Self> {
+		let header = bytes.get(0..4)?;
+		if header[0] != 0xFF || header[1] & 0xE0 != 0xE0 {
+			return None;
+		}
+		let (mpeg1, rate_divisor) = match (header[1] >> 3) & 0x03 {
+			3 => (true, 1),
+			2 => (false, 2),
+			0 => (false, 4), // MPEG 2.5
+			_ => return None,
+		};
+		let layer = match (header[1] >> 1) & 0x03 {
+			3 => 1,
+			2 => 2,
+			1 => 3,
+			_ => return None,
+		};
+		let bitrates = match (mpeg1, layer) {
+			(true, 1) => &BITRATES_V1_L1,
+			(true, 2) => &BITRATES_V1_L2,
+			(true, _) => &BITRATES_V1_L3,
+			(false, 1) => &BITRATES_V2_L1,
+			(false, _) => &BITRATES_V2_L23,
+		};
+		let bitrate = *bitrates.get((header[2] >> 4) as usize)?;
+		let samples_rate = match (header[2] >> 2) & 0x03 {
+			0 => 44100,
+			1 => 48000,
+			2 => 32000,
+			_ => return None,
+		} / rate_divisor;
+
+		Some(FrameHeader {
+			mpeg1,
+			layer,
+			mono: header[3] >> 6 == 3,
+			bitrate,
+			samples_rate,
+		})
+	}
 
-			duration,
+	fn samples_per_frame(&self) -> u64 {
+		match self.layer {
+			1 => 384,
+			3 if !self.mpeg1 => 576,
+			_ => 1152,
 		}
 	}
+
+	// Number of frames from a Xing/Info or VBRI header, if the first frame is one
+	// Those are only written for layer III, after the side info or at a fixed offset
+	fn frame_count(&self, frame: &[u8]) -> Option<u64> {
+		if self.layer != 3 {
+			return None;
+		}
+		let side_info = match (self.mpeg1, self.mono) {
+			(true, false) => 32,
+			(true, true) | (false, false) => 17,
+			(false, true) => 9,
+		};
+		let xing = frame.get(4 + side_info..)?;
+		if xing.starts_with(b"Xing") || xing.starts_with(b"Info") {
+			let flags = read_u32_be(xing, 4)?;
+			return if flags & 1 != 0 { read_u32_be(xing, 8).map(u64::from) } else { None };
+		}
+		let vbri = frame.get(4 + 32..)?;
+		if vbri.starts_with(b"VBRI") {
+			return read_u32_be(vbri, 14).map(u64::from);
+		}
+		None
+	}
+}
+
+fn read_u32_be(bytes: &[u8], pos: usize) -> Option<u32> {
+	let bytes = bytes.get(pos..pos + 4)?;
+	Some((bytes[0] as u32) << 24 | (bytes[1] as u32) << 16 | (bytes[2] as u32) << 8 | bytes[3] as u32)
+}
+
+// Skips past an ID3v2 tag at the start, if there is one
+fn audio_start(bytes: &[u8]) -> usize {
+	match bytes.get(0..10) {
+		Some(tag) if tag.starts_with(b"ID3") => {
+			// Syncsafe, 7 bits per byte
+			let size = tag[6..10].iter().fold(0, |acc, &byte| acc << 7 | (byte & 0x7F) as usize);
+			let footer = if tag[5] & 0x10 != 0 { 10 } else { 0 };
+			10 + size + footer
+		}
+		_ => 0,
+	}
+}
+
+// Without decoding anything: the frame count from a Xing/Info or VBRI header,
+// or for files without one (usually constant bitrate), the size over the bitrate
+pub fn header_duration(bytes: &[u8]) -> Option<Duration> {
+	let start = audio_start(bytes);
+	let frame = bytes.get(start..)?;
+	let header = FrameHeader::parse(frame)?;
+	if header.samples_rate == 0 {
+		return None;
+	}
+
+	if let Some(frames) = header.frame_count(frame) {
+		// The decoder plays the header frame as silence, so it counts too
+		let samples = (frames + 1) * header.samples_per_frame();
+		let nanos = samples * 1_000_000_000 / header.samples_rate as u64;
+		return Some(Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32));
+	}
+
+	if header.bitrate == 0 {
+		return None;
+	}
+	// An ID3v1 tag at the end isn't audio
+	let mut size = frame.len();
+	if size >= 128 && frame[size - 128..].starts_with(b"TAG") {
+		size -= 128;
+	}
+	let nanos = size as u64 * 8_000_000 / header.bitrate as u64;
+	Some(Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32))
 }
 
 impl<R> Source for Mp3Decoder<R>
@@ -74,7 +200,7 @@ where
 
 	#[inline]
 	fn total_duration(&self) -> Option<Duration> {
-		Some(self.duration)
+		None
 	}
 }
 
@@ -86,7 +212,7 @@ where
 
 	#[inline]
 	fn next(&mut self) -> Option<i16> {
-		if self.current_frame.samples[0].len() == 0 {
+		if self.current_frame.samples[0].is_empty() {
 			return None;
 		}
 
@@ -122,7 +248,7 @@ where
 		self.current_frame_channel = 0;
 		self.current_frame_sample_pos = 0;
 
-		return Some(sample);
+		Some(sample)
 	}
 
 	#[inline]
@@ -137,7 +263,7 @@ where
 	R: Read,
 {
 	let frame = decoder.filter_map(|f| f.ok()).next();
-	let frame = frame.unwrap_or_else(|| simplemad::Frame {
+	frame.unwrap_or_else(|| simplemad::Frame {
 		sample_rate: 44100,
 		bit_rate: 0,
 		samples: vec![Vec::new()],
@@ -145,9 +271,7 @@ where
 		duration: Duration::new(0, 0),
 		layer: Default::default(),
 		mode: Default::default(),
-	});
-
-	frame
+	})
 }
 
 /// Returns true if the stream contains MP3 data, then resets it to where it was.
@@ -155,7 +279,7 @@ fn is_mp3<R>(mut data: R) -> bool
 where
 	R: Read + Seek,
 {
-	let stream_pos = data.seek(SeekFrom::Current(0)).unwrap();
+	let stream_pos = data.stream_position().unwrap();
 
 	if simplemad::Decoder::decode(data.by_ref()).is_err() {
 		data.seek(SeekFrom::Start(stream_pos)).unwrap();
@@ -165,3 +289,68 @@ where
 	data.seek(SeekFrom::Start(stream_pos)).unwrap();
 	true
 }
+
+#[cfg(test)]
+mod tests {
+	use std::time::Duration;
+
+	use super::header_duration;
+
+	// MPEG 1 layer III, 128kbps, 48000Hz, stereo
+	const HEADER: [u8; 4] = [0xFF, 0xFB, 0x94, 0x00];
+
+	fn first_frame(tag: &[u8]) -> Vec<u8> {
+		let mut frame = HEADER.to_vec();
+		// Side info
+		frame.extend_from_slice(&[0; 32]);
+		frame.extend_from_slice(tag);
+		frame.resize(384, 0);
+		frame
+	}
+
+	#[test]
+	fn xing_frame_count() {
+		let frame = first_frame(b"Xing\0\0\0\x01\0\0\0\x63");
+		// 99 frames and the Xing one, 1152 samples each
+		assert_eq!(header_duration(&frame), Some(Duration::from_millis(2400)));
+	}
+
+	#[test]
+	fn xing_without_frame_count() {
+		// Falls back to the bitrate, 384 bytes at 128kbps
+		let frame = first_frame(b"Xing\0\0\0\0");
+		assert_eq!(header_duration(&frame), Some(Duration::from_millis(24)));
+	}
+
+	#[test]
+	fn vbri_frame_count() {
+		let frame = first_frame(b"VBRI\0\x01\0\0\0\0\0\0\0\0\0\0\0\x63");
+		assert_eq!(header_duration(&frame), Some(Duration::from_millis(2400)));
+	}
+
+	#[test]
+	fn skips_id3v2() {
+		let mut mp3 = b"ID3\x03\0\0\0\0\0\x0A".to_vec();
+		mp3.extend_from_slice(&[0; 10]);
+		mp3.extend_from_slice(&first_frame(b"Info\0\0\0\x01\0\0\0\x63"));
+		assert_eq!(header_duration(&mp3), Some(Duration::from_millis(2400)));
+	}
+
+	#[test]
+	fn constant_bitrate() {
+		let mut mp3 = HEADER.to_vec();
+		mp3.resize(16000, 0);
+		assert_eq!(header_duration(&mp3), Some(Duration::from_secs(1)));
+
+		// An ID3v1 tag at the end doesn't count
+		mp3.extend_from_slice(b"TAG");
+		mp3.resize(16000 + 128, 0);
+		assert_eq!(header_duration(&mp3), Some(Duration::from_secs(1)));
+	}
+
+	#[test]
+	fn not_a_frame() {
+		assert_eq!(header_duration(b"RIFF\0\0\0\0WAVE"), None);
+		assert_eq!(header_duration(&[]), None);
+	}
+}
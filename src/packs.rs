@@ -0,0 +1,145 @@
+// Keeps track of which packs are loaded, and loads more in the background while things keep playing
+// Anything dropped into respacks/ gets picked up, and anything taken out gets unloaded
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use archive;
+use loader::{self, LoadStatus, PackInfo};
+
+const PACK_DIR: &str = "respacks";
+const SCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+pub struct Packs {
+	// Loaded or still loading
+	loaded: HashSet<PathBuf>,
+	// Unloaded by hand, these don't come back until a reload
+	disabled: HashSet<PathBuf>,
+	// For the ones that have finished loading
+	info: HashMap<PathBuf, PackInfo>,
+
+	// Everything that's been seen in the folder
+	known: HashSet<PathBuf>,
+	// New in the folder, with the size last time - waits until it stops being copied in
+	appeared: HashMap<PathBuf, u64>,
+	last_scan: Instant,
+
+	tx: Sender<LoadStatus>,
+	rx: Receiver<LoadStatus>,
+}
+
+impl Packs {
+	// Whatever's in the folder already only gets loaded if it's asked for
+	pub fn new() -> Self {
+		let (tx, rx) = channel();
+		Packs {
+			loaded: HashSet::new(),
+			disabled: HashSet::new(),
+			info: HashMap::new(),
+
+			known: scan_folder().into_iter().collect(),
+			appeared: HashMap::new(),
+			last_scan: Instant::now(),
+
+			tx,
+			rx,
+		}
+	}
+
+	// Does nothing if it's already loaded or on the way
+	pub fn load(&mut self, path: PathBuf) {
+		self.disabled.remove(&path);
+		if self.loaded.contains(&path) {
+			return;
+		}
+		println!("Loading {}", archive::pack_name(&path));
+		self.loaded.insert(path.clone());
+
+		let tx = self.tx.clone();
+		thread::spawn(move || loader::load_respack(path, tx).map_err(|err| println!("Error loading pack: {}", err)));
+	}
+
+	pub fn try_recv(&self) -> Option<LoadStatus> {
+		self.rx.try_recv().ok()
+	}
+
+	// Whether a pack that's finished loading should be added
+	// Not if it was unloaded or taken out of the folder while it loaded, or it's already been added
+	pub fn accept(&self, path: &Path) -> bool {
+		self.loaded.contains(path) && !self.info.contains_key(path)
+	}
+
+	pub fn set_info(&mut self, path: &Path, info: PackInfo) {
+		self.info.insert(path.to_owned(), info);
+	}
+
+	pub fn info(&self, path: &Path) -> Option<&PackInfo> {
+		self.info.get(path)
+	}
+
+	// Loads anything new in the folder, and returns the packs that have gone and need unloading
+	pub fn update(&mut self) -> Vec<PathBuf> {
+		if self.last_scan.elapsed() < SCAN_INTERVAL {
+			return Vec::new();
+		}
+		self.last_scan = Instant::now();
+
+		for path in scan_folder() {
+			if self.known.contains(&path) {
+				continue;
+			}
+			let size = folder_size(&path);
+			if self.appeared.get(&path) == Some(&size) {
+				self.appeared.remove(&path);
+				self.known.insert(path.clone());
+				if !self.disabled.contains(&path) {
+					self.load(path);
+				}
+			} else {
+				self.appeared.insert(path, size);
+			}
+		}
+
+		let gone: Vec<PathBuf> = self.loaded.iter().filter(|path| !path.exists()).cloned().collect();
+		for path in &gone {
+			self.loaded.remove(path);
+			self.known.remove(path);
+			self.info.remove(path);
+		}
+		gone
+	}
+
+	pub fn unload(&mut self, path: &Path) {
+		println!("Unloading {}", archive::pack_name(path));
+		self.loaded.remove(path);
+		self.info.remove(path);
+		self.disabled.insert(path.to_owned());
+	}
+
+	// Brings back unloaded packs, and loads everything else in the folder without waiting
+	pub fn reload(&mut self) {
+		let disabled: Vec<PathBuf> = self.disabled.drain().collect();
+		for path in disabled {
+			self.load(path);
+		}
+
+		for path in scan_folder() {
+			self.known.insert(path.clone());
+			self.appeared.remove(&path);
+			self.load(path);
+		}
+	}
+}
+
+fn scan_folder() -> Vec<PathBuf> {
+	archive::list_respacks(PACK_DIR)
+}
+
+// Folders just go by the size of the entry, which changes as files get added
+fn folder_size(path: &Path) -> u64 {
+	fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)
+}
@@ -0,0 +1,146 @@
+// Renders a song offline, with no window or audio device
+// Frames come out as a png sequence or raw RGBA on stdout, for previews or piping into an encoder
+//
+// See cli::USAGE for the options
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use sdl2;
+use sdl2::image::SaveSurface;
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::render::BlendMode;
+use sdl2::surface::Surface;
+
+use archive;
+use config::Config;
+use clock;
+use loader::{self, LoadStatus};
+use ui::UiLayout;
+use layouts::Layout;
+use images::ImageManager;
+use songs::SongManager;
+use screen::Screen;
+use Result;
+
+pub enum Output {
+	Frames(PathBuf),
+	Raw,
+}
+
+pub struct RenderOptions {
+	pub fps: u32,
+	// Defaults to the buildup and one loop
+	pub length: Option<f64>,
+	pub output: Output,
+}
+
+impl Default for RenderOptions {
+	fn default() -> Self {
+		RenderOptions {
+			fps: 30,
+			length: None,
+			output: Output::Raw,
+		}
+	}
+}
+
+pub fn run(options: &RenderOptions, config: &Config) -> Result<()> {
+	let (width, height) = config.window_size();
+
+	if let Output::Frames(ref dir) = options.output {
+		::std::fs::create_dir_all(dir)?;
+	}
+
+	// Only need ttf and image, no video or audio
+	let _sdl_context = sdl2::init()?;
+	let ttf_context = sdl2::ttf::init()?;
+	sdl2::image::init(sdl2::image::INIT_PNG | sdl2::image::INIT_JPG | sdl2::image::INIT_WEBP)?;
+	let font = ttf_context.load_font(config.font(), 12)?;
+
+	// Everything from here on goes by the simulated clock
+	clock::simulate();
+
+	let surface = Surface::new(width, height, PixelFormatEnum::ABGR8888)?;
+	let mut canvas = surface.into_canvas()?;
+	canvas.set_blend_mode(BlendMode::Blend);
+	let texture_creator = canvas.texture_creator();
+
+	let mut image_manager = ImageManager::new(&texture_creator);
+	let mut song_manager = SongManager::headless();
+	song_manager.set_buildup_mode(config.buildup_mode());
+
+	// No loading screen, so just load everything here
+	let (tx, rx) = channel();
+	for path in config.respack_paths() {
+		eprintln!("Loading {}", archive::pack_name(&path));
+		loader::load_respack(path, tx.clone())?;
+	}
+	drop(tx);
+	for status in rx.iter() {
+		match status {
+			LoadStatus::Failed(err) => eprintln!("Error loading pack: {}", err),
+			LoadStatus::Done(pack) => {
+				image_manager.extend(&pack.path, pack.images);
+				song_manager.extend(&pack.path, pack.songs);
+			}
+			_ => {}
+		}
+	}
+
+	let mut screen = Screen::new(&texture_creator);
+	let mut ui = Layout::new(config.layout(), &font, &texture_creator);
+	let mut rng = ::new_rng(config.seed);
+
+	image_manager.random_image(&mut rng, &mut ui);
+	match config.song {
+		Some(ref song) => song_manager.play_song(song, &mut ui)?,
+		None => song_manager.play_random(&mut rng, &mut ui),
+	}
+
+	let length = match options.length {
+		Some(length) => length,
+		None => song_manager.song_length().map(::duration_to_secs).ok_or("No songs to render")?,
+	};
+	let num_frames = (length * options.fps as f64).ceil() as usize;
+	let frame_time = Duration::new(0, 1_000_000_000 / options.fps);
+
+	let stdout = io::stdout();
+	let mut stdout = stdout.lock();
+
+	for frame in 0..num_frames {
+		song_manager.update_beat(&mut screen, &mut image_manager, &mut rng, &mut ui);
+
+		screen.clear(&mut canvas);
+		image_manager.draw_image(&mut canvas, &mut ui);
+		ui.draw(&mut canvas)?;
+		screen.draw(&mut canvas);
+
+		let mut pixels = canvas.read_pixels(None, PixelFormatEnum::ABGR8888)?;
+		match options.output {
+			Output::Frames(ref dir) => save_frame(&mut pixels, (width, height), dir, frame)?,
+			Output::Raw => stdout.write_all(&pixels)?,
+		}
+
+		clock::advance(frame_time);
+	}
+
+	stdout.flush()?;
+	eprintln!("Rendered {} frames", num_frames);
+
+	Ok(())
+}
+
+fn save_frame(pixels: &mut [u8], (width, height): (u32, u32), dir: &Path, frame: usize) -> Result<()> {
+	let surface = Surface::from_data(
+		pixels,
+		width,
+		height,
+		width * 4,
+		PixelFormatEnum::ABGR8888,
+	)?;
+	surface.save(dir.join(format!("frame_{:06}.png", frame)))?;
+	Ok(())
+}
@@ -0,0 +1,112 @@
+// hues repack RESPACK OUTPUT - loads a pack and writes it back out as a zip or a folder
+// Then loads what it wrote and lists anything that came back different
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc::channel;
+
+use sdl2;
+
+use config::Config;
+use loader::{self, ImageLoader, LoadStatus, ResPack};
+use songs::Song;
+use writer;
+use Result;
+
+pub fn run(config: &Config, output: &Path) -> Result<()> {
+	sdl2::image::init(sdl2::image::INIT_PNG | sdl2::image::INIT_JPG | sdl2::image::INIT_WEBP)?;
+
+	let paths = config.respack_paths();
+	if paths.len() != 1 {
+		return Err("Need exactly one pack to repack".into());
+	}
+
+	let pack = load(&paths[0])?;
+	writer::write_respack(&pack, output)?;
+	println!("Wrote {}", output.display());
+
+	let written = load(output)?;
+	let differences = compare(&pack, &written);
+	for difference in &differences {
+		println!("{}", difference);
+	}
+	if !differences.is_empty() {
+		return Err(format!("{} differences after loading it again", differences.len()).into());
+	}
+	Ok(())
+}
+
+// Anything that goes wrong on the way gets printed, it only fails if the whole pack does
+fn load(path: &Path) -> Result<ResPack> {
+	let (tx, rx) = channel();
+	loader::load_respack(path, tx)?;
+
+	let mut pack = None;
+	for status in rx.iter() {
+		match status {
+			LoadStatus::Failed(error) => eprintln!("{}", error),
+			LoadStatus::Done(loaded) => pack = Some(loaded),
+			_ => {}
+		}
+	}
+	pack.ok_or_else(|| format!("Could not load {}", path.display()).into())
+}
+
+fn compare(old: &ResPack, new: &ResPack) -> Vec<String> {
+	let mut differences = Vec::new();
+
+	let (old_info, new_info) = (&old.info, &new.info);
+	if (old_info.name(), old_info.author(), old_info.description(), old_info.link())
+		!= (new_info.name(), new_info.author(), new_info.description(), new_info.link())
+	{
+		differences.push("Pack info".to_owned());
+	}
+
+	// By name, so it still matches if the files were stored in a different order
+	let new_songs: HashMap<&str, &Song> = new.songs.iter().map(|song| (song.name(), song)).collect();
+	for song in &old.songs {
+		match new_songs.get(song.name()) {
+			Some(new_song) if same_song(song, new_song) => {}
+			Some(_) => differences.push(format!("Song {} changed", song.name())),
+			None => differences.push(format!("Song {} is missing", song.name())),
+		}
+	}
+	if new.songs.len() > old.songs.len() {
+		differences.push(format!("{} songs more than before", new.songs.len() - old.songs.len()));
+	}
+
+	let new_images: HashMap<&str, &ImageLoader> = new.images.iter().map(|image| (image.name.as_ref(), image)).collect();
+	for image in &old.images {
+		match new_images.get(image.name.as_str()) {
+			Some(new_image) if same_image(image, new_image) => {}
+			Some(_) => differences.push(format!("Image {} changed", image.name)),
+			None => differences.push(format!("Image {} is missing", image.name)),
+		}
+	}
+	if new.images.len() > old.images.len() {
+		differences.push(format!("{} images more than before", new.images.len() - old.images.len()));
+	}
+
+	differences
+}
+
+fn same_song(old: &Song, new: &Song) -> bool {
+	let buildup = |song: &Song| song.buildup().map(|(name, audio)| (name.to_owned(), audio.data().to_vec()));
+	old.title == new.title
+		&& old.source == new.source
+		&& old.rhythm == new.rhythm
+		&& old.buildup_rhythm == new.buildup_rhythm
+		&& old.loop_audio().data() == new.loop_audio().data()
+		&& buildup(old) == buildup(new)
+}
+
+fn same_image(old: &ImageLoader, new: &ImageLoader) -> bool {
+	old.fullname == new.fullname
+		&& old.source == new.source
+		&& old.source_other == new.source_other
+		&& old.align == new.align
+		&& old.frame_durations == new.frame_durations
+		&& old.beats_per_anim == new.beats_per_anim
+		&& old.frames.len() == new.frames.len()
+		&& old.files == new.files
+}
@@ -1,20 +1,46 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
+use std::os::raw::c_int;
 
 use sdl2::pixels::{Color as Colour, PixelFormatEnum};
 use sdl2::surface::Surface;
-use sdl2::render::{Texture, TextureCreator, WindowCanvas as Canvas};
+use sdl2::render::{BlendMode, Canvas, RenderTarget, Texture, TextureCreator};
+use sdl2::rect::Rect;
+use sdl2::sys;
 
-use rand::{thread_rng as rng, Rng};
+use rand::Rng;
 
 use ui::UiLayout;
+use beats::{Direction, Trippy};
 use duration_to_secs;
+use clock;
+use Random;
+
+// Seconds
+const TRIPPY_LENGTH: f64 = 0.5;
+
+// A blackout start far enough back that the fade is already over
+fn faded_in() -> Instant {
+	let now = clock::now();
+	now.checked_sub(Duration::from_secs(1)).unwrap_or(now)
+}
 
 pub struct Screen {
 	colour: Colour,
+	// Index into HUES, for the UI
+	colour_index: usize,
 
 	full_black: bool,
 	blackout_init: Option<Instant>,
 	blackout_texture: Texture,
+	// Black or white
+	blackout_colour: Colour,
+
+	inverted: bool,
+	trippy: Option<(Trippy, Instant)>,
+	// Wipes away the previous colour
+	shutter: Option<Shutter>,
+	// Start and length in seconds
+	flash: Option<(Instant, f64)>,
 
 	fade_init: Option<Instant>,
 	fade_colour: Colour,
@@ -26,42 +52,33 @@ impl Screen {
 	pub fn new<T>(texture_creator: &TextureCreator<T>) -> Self {
 		Screen {
 			colour: Colour::RGBA(0x00, 0x00, 0x00, 0xFF),
+			colour_index: 0,
 
 			// Don't start off with an actual blackout
 			full_black: false,
 			blackout_init: None,
-			blackout_texture: {
-				// unsure whether large or small texture is good
-				let mut surface = Surface::new(1280, 720, PixelFormatEnum::RGBA8888).unwrap();
-				surface
-					.fill_rect(None, Colour::RGBA(0x00, 0x00, 0x00, 0xFF))
-					.unwrap();
-				texture_creator
-					.create_texture_from_surface(surface)
-					.unwrap()
-			},
-
-			fade_texture: {
-				let mut surface = Surface::new(1280, 720, PixelFormatEnum::RGBA8888).unwrap();
-				surface
-					.fill_rect(None, Colour::RGBA(0xFF, 0xFF, 0xFF, 0xFF))
-					.unwrap();
-				texture_creator
-					.create_texture_from_surface(surface)
-					.unwrap()
-			},
+			// White so it can be coloured to black or white
+			blackout_texture: white_texture(texture_creator),
+			blackout_colour: Colour::RGB(0x00, 0x00, 0x00),
+
+			inverted: false,
+			trippy: None,
+			shutter: None,
+			flash: None,
+
+			fade_texture: white_texture(texture_creator),
 			fade_init: None,
 			fade_end: 1.0,
 			fade_colour: Colour::RGBA(0xFF, 0xFF, 0xFF, 0xFF),
 		}
 	}
 
-	pub fn clear(&mut self, canvas: &mut Canvas) {
+	pub fn clear<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
 		canvas.set_draw_color(self.colour);
 		canvas.clear();
 
 		if let Some(start) = self.fade_init {
-			let fade = duration_to_secs(start.elapsed()) / self.fade_end;
+			let fade = duration_to_secs(clock::elapsed(start)) / self.fade_end;
 
 			if fade >= 1.0 {
 				self.colour = self.fade_colour;
@@ -78,54 +95,133 @@ impl Screen {
 	}
 
 	// I think I might need to change these to trait objects later if I'm serious
-	pub fn random_colour<T: UiLayout>(&mut self, ui: &mut T) {
-		let idx = rng().gen_range(0x00, HUES.len());
+	pub fn random_colour<T: UiLayout>(&mut self, rng: &mut Random, ui: &mut T) {
+		let idx = rng.gen_range(0x00, HUES.len());
 		let (hue, name) = HUES[idx];
 
 		//ui.update_colour_index(idx);
 		//ui.update_colour_name(name);
 		ui.update_colour(idx, name);
 		self.colour = hue;
+		self.colour_index = idx;
 	}
 
 	// Length is in seconds
-	pub fn fade_random<T: UiLayout>(&mut self, length: f64, ui: &mut T) {
-		let idx = rng().gen_range(0x00, HUES.len());
+	pub fn fade_random<T: UiLayout>(&mut self, length: f64, rng: &mut Random, ui: &mut T) {
+		let idx = rng.gen_range(0x00, HUES.len());
 		let (Colour {r, g, b, a}, name) = HUES[idx];
 
 		ui.update_colour(idx, name);
+		self.colour_index = idx;
 		self.fade_texture.set_color_mod(r, g, b);
 		self.fade_colour = Colour { r, g, b, a };
-		self.fade_init = Some(Instant::now());
+		self.fade_init = Some(clock::now());
 		self.fade_end = length;
 	}
 
+	// For when the layout changes
+	pub fn update_ui<T: UiLayout>(&self, ui: &mut T) {
+		let (_, name) = HUES[self.colour_index];
+		ui.update_colour(self.colour_index, name);
+	}
+
 	pub fn clear_blackout(&mut self) {
 		self.blackout_init = None;
 		self.full_black = false;
 	}
 
 	pub fn blackout(&mut self) {
-		self.blackout_init = Some(Instant::now());
+		self.set_blackout_colour(Colour::RGB(0x00, 0x00, 0x00));
+		self.blackout_init = Some(clock::now());
+	}
+
+	pub fn whiteout(&mut self) {
+		self.set_blackout_colour(Colour::RGB(0xFF, 0xFF, 0xFF));
+		self.blackout_init = Some(clock::now());
 	}
 
 	pub fn short_blackout(&mut self) {
+		self.set_blackout_colour(Colour::RGB(0x00, 0x00, 0x00));
+		self.full_black = true;
+	}
+
+	pub fn short_whiteout(&mut self) {
+		self.set_blackout_colour(Colour::RGB(0xFF, 0xFF, 0xFF));
 		self.full_black = true;
 	}
 
-	pub fn draw(&mut self, canvas: &mut Canvas) {
+	// Same as blackout/whiteout, just already faded in
+	pub fn instant_blackout(&mut self) {
+		self.set_blackout_colour(Colour::RGB(0x00, 0x00, 0x00));
+		self.blackout_init = Some(faded_in());
+	}
+
+	pub fn instant_whiteout(&mut self) {
+		self.set_blackout_colour(Colour::RGB(0xFF, 0xFF, 0xFF));
+		self.blackout_init = Some(faded_in());
+	}
+
+	fn set_blackout_colour(&mut self, colour: Colour) {
+		self.blackout_colour = colour;
+		self.blackout_texture.set_color_mod(colour.r, colour.g, colour.b);
+	}
+
+	// Length is in seconds
+	pub fn flash(&mut self, length: f64) {
+		self.clear_blackout();
+		self.flash = Some((clock::now(), length));
+	}
+
+	pub fn invert(&mut self) {
+		self.inverted = !self.inverted;
+	}
+
+	pub fn trippy(&mut self, trippy: Trippy) {
+		self.trippy = Some((trippy, clock::now()));
+	}
+
+	// Call before changing the colour - length is in seconds
+	pub fn shutter(&mut self, direction: Direction, length: f64) {
+		self.shutter = Some(Shutter {
+			direction,
+			colour: self.colour,
+			init: clock::now(),
+			length,
+		});
+	}
+
+	pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
+		self.draw_shutter(canvas);
+		self.draw_trippy(canvas);
+
+		if self.inverted {
+			let (width, height) = canvas.output_size().unwrap();
+			invert_rects(canvas, &[Rect::new(0, 0, width, height)]);
+		}
+
+		if let Some((start, length)) = self.flash {
+			let progress = duration_to_secs(clock::elapsed(start)) / length;
+			if progress >= 1.0 {
+				self.flash = None;
+			} else {
+				let alpha = ((1.0 - progress) * 255.0) as u8;
+				canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, alpha));
+				canvas.fill_rect(None).unwrap();
+			}
+		}
+
 		if self.full_black {
-			canvas.set_draw_color(Colour::RGB(0x00, 0x00, 0x00));
+			canvas.set_draw_color(self.blackout_colour);
 			canvas.fill_rect(None).unwrap();
 			return;
 		}
 
 		if let Some(start) = self.blackout_init {
-			let fade = duration_to_secs(start.elapsed()) * 10.0;
+			let fade = duration_to_secs(clock::elapsed(start)) * 10.0;
 			// Maybe set a flag to check before drawing image
 			// TODO: ^ do that
 			if fade >= 1.0 {
-				canvas.set_draw_color(Colour::RGB(0x00, 0x00, 0x00));
+				canvas.set_draw_color(self.blackout_colour);
 				canvas.fill_rect(None).unwrap();
 			} else {
 				let alpha = (fade * 256.0) as u8;
@@ -134,6 +230,127 @@ impl Screen {
 			}
 		}
 	}
+
+	fn draw_shutter<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
+		let progress = match self.shutter {
+			Some(ref shutter) => duration_to_secs(clock::elapsed(shutter.init)) / shutter.length,
+			None => return,
+		};
+		if progress >= 1.0 {
+			self.shutter = None;
+			return;
+		}
+
+		if let Some(ref shutter) = self.shutter {
+			let (width, height) = canvas.output_size().unwrap();
+			// The part that still has the old colour
+			let w = (width as f64 * (1.0 - progress)) as u32;
+			let h = (height as f64 * (1.0 - progress)) as u32;
+			let rect = match shutter.direction {
+				Direction::Left => Rect::new(0, 0, w.max(1), height),
+				Direction::Right => Rect::new((width - w) as i32, 0, w.max(1), height),
+				Direction::Up => Rect::new(0, 0, width, h.max(1)),
+				Direction::Down => Rect::new(0, (height - h) as i32, width, h.max(1)),
+			};
+			canvas.set_draw_color(shutter.colour);
+			canvas.fill_rect(rect).unwrap();
+		}
+	}
+
+	// A circle of inverted colour that grows or shrinks
+	fn draw_trippy<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) {
+		let (trippy, progress) = match self.trippy {
+			Some((trippy, start)) => (trippy, duration_to_secs(clock::elapsed(start)) / TRIPPY_LENGTH),
+			None => return,
+		};
+		if progress >= 1.0 {
+			self.trippy = None;
+			return;
+		}
+
+		let (width, height) = canvas.output_size().unwrap();
+		let max_radius = ((width * width + height * height) as f64).sqrt() / 2.0;
+		let radius = match trippy {
+			Trippy::In => max_radius * (1.0 - progress),
+			Trippy::Out => max_radius * progress,
+		};
+
+		let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+		let rects: Vec<Rect> = (0..height)
+			.filter_map(|y| {
+				let dy = y as f64 + 0.5 - cy;
+				if dy.abs() >= radius {
+					return None;
+				}
+				let dx = (radius * radius - dy * dy).sqrt();
+				let left = (cx - dx).max(0.0) as i32;
+				let right = (cx + dx).min(width as f64) as i32;
+				if right <= left {
+					None
+				} else {
+					Some(Rect::new(left, y as i32, (right - left) as u32, 1))
+				}
+			})
+			.collect();
+
+		invert_rects(canvas, &rects);
+	}
+}
+
+// Only ever stretched over the whole screen, so it doesn't need to be any bigger
+fn white_texture<T>(texture_creator: &TextureCreator<T>) -> Texture {
+	let mut surface = Surface::new(16, 16, PixelFormatEnum::RGBA8888).unwrap();
+	surface
+		.fill_rect(None, Colour::RGBA(0xFF, 0xFF, 0xFF, 0xFF))
+		.unwrap();
+	texture_creator
+		.create_texture_from_surface(surface)
+		.unwrap()
+}
+
+struct Shutter {
+	direction: Direction,
+	colour: Colour,
+	init: Instant,
+	length: f64, // seconds
+}
+
+// The sdl2 crate only knows about the built in blend modes, and the sys enum can't hold a custom one
+extern "C" {
+	fn SDL_ComposeCustomBlendMode(
+		src_colour_factor: u32,
+		dst_colour_factor: u32,
+		colour_operation: u32,
+		src_alpha_factor: u32,
+		dst_alpha_factor: u32,
+		alpha_operation: u32,
+	) -> u32;
+	fn SDL_SetRenderDrawBlendMode(renderer: *mut sys::SDL_Renderer, mode: u32) -> c_int;
+}
+
+// Fill with white using dst = 1 - dst, which inverts whatever is underneath
+fn invert_rects<T: RenderTarget>(canvas: &mut Canvas<T>, rects: &[Rect]) {
+	if rects.is_empty() {
+		return;
+	}
+
+	unsafe {
+		let mode = SDL_ComposeCustomBlendMode(
+			sys::SDL_BlendFactor::SDL_BLENDFACTOR_ONE_MINUS_DST_COLOR as u32,
+			sys::SDL_BlendFactor::SDL_BLENDFACTOR_ZERO as u32,
+			sys::SDL_BlendOperation::SDL_BLENDOPERATION_ADD as u32,
+			sys::SDL_BlendFactor::SDL_BLENDFACTOR_ZERO as u32,
+			sys::SDL_BlendFactor::SDL_BLENDFACTOR_ONE as u32,
+			sys::SDL_BlendOperation::SDL_BLENDOPERATION_ADD as u32,
+		);
+		SDL_SetRenderDrawBlendMode(canvas.raw(), mode);
+	}
+
+	canvas.set_draw_color(Colour::RGB(0xFF, 0xFF, 0xFF));
+	canvas.fill_rects(rects).unwrap();
+
+	// Canvas Blendmode should be Blend
+	canvas.set_blend_mode(BlendMode::Blend);
 }
 
 // 0x40 hues
@@ -1,26 +1,29 @@
 use std::fmt;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use rand::{thread_rng as rng, Rng};
-
-use sdl2::video::WindowContext;
+use rand::Rng;
 
 use rodio;
 use rodio::{Endpoint, Sink, Source};
-use rodio::source::Zero as ZeroSource;
 
 use duration_to_secs;
-use AudioData;
+use Random;
+use audio::{self, Counted, EncodedAudio, PlaybackClock};
+use clock;
 use Screen;
 use ui::UiLayout;
 use images::ImageManager;
 use loader::SongData;
+use beats::{self, BeatEffect, Blackout, BlurDirection, ColourChange};
 
 use Result;
 
 pub struct Song {
 	name: String,
+	// Path of the pack it came from
+	pack: PathBuf,
 	pub title: String,
 	pub source: Option<String>,
 	pub rhythm: Vec<char>,
@@ -36,70 +39,251 @@ pub struct Song {
 	loop_duration: Duration,
 	buildup_duration: Duration,
 
-	loop_audio: AudioData,
-	buildup_audio: Option<AudioData>,
+	// Not decoded until it's played
+	loop_audio: EncodedAudio,
+	buildup_audio: Option<EncodedAudio>,
+}
+
+// Whether songs start with their buildup
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BuildupMode {
+	Always,
+	// Only the first time each song is played
+	Once,
+	Never,
 }
 
-fn empty_audio() -> AudioData {
-	let source = Box::new(ZeroSource::new(2, 44100)) as Box<Source<Item = i16> + Send>;
-	source.buffered()
+impl BuildupMode {
+	pub fn from_str(name: &str) -> Option<Self> {
+		match name.to_lowercase().as_ref() {
+			"always" | "on" => Some(BuildupMode::Always),
+			"once" => Some(BuildupMode::Once),
+			"never" | "off" => Some(BuildupMode::Never),
+			_ => None,
+		}
+	}
 }
 
 pub struct SongManager {
 	songs: Vec<Song>,
 	curr_index: Option<usize>,
 
-	music_track: Sink,
-	beat_time: Instant,
+	// No sink or endpoint when there's no audio device (e.g. rendering offline)
+	music_track: Option<Sink>,
+	// How much of the current song has been sent to the audio device
+	clock: Option<PlaybackClock>,
+	// Time between samples leaving the sink and actually being heard
+	latency: Duration,
+	// Or the visuals can be set to run ahead instead
+	negative_latency: bool,
+	volume: f32,
+	muted: bool,
+
+	// The last song, fading out under the new one
+	fading: Option<Sink>,
+	// When the last song change happened, if it's still fading
+	transition: Option<Instant>,
+	fade_length: Duration,
+	// Keep the screen black until the fade is done
+	blackout_on_change: bool,
+	blacked_out: bool,
 
 	beat_index: Option<BeatIndex>,
 
-	endpoint: Endpoint,
+	buildup_mode: BuildupMode,
+	// Songs that have had their buildup played, by name
+	played_buildups: HashSet<String>,
+	// Where the current song started, after the buildup if it got skipped
+	song_start: Duration,
+
+	endpoint: Option<Endpoint>,
 }
 
 impl SongManager {
 	pub fn new() -> Self {
 		let endpoint = rodio::default_endpoint().unwrap();
+		SongManager {
+			music_track: Some(Sink::new(&endpoint)),
+			endpoint: Some(endpoint),
+			..SongManager::headless()
+		}
+	}
+
+	// Doesn't play anything, beats just follow the clock
+	pub fn headless() -> Self {
 		SongManager {
 			songs: Vec::new(),
 			curr_index: None,
 
-			beat_time: Instant::now(),
+			clock: None,
+			latency: Duration::new(0, 0),
+			negative_latency: false,
+			volume: 1.0,
+			muted: false,
+
+			fading: None,
+			transition: None,
+			fade_length: Duration::from_millis(500),
+			blackout_on_change: false,
+			blacked_out: false,
+
 			beat_index: None,
 
-			music_track: Sink::new(&endpoint),
-			endpoint,
+			buildup_mode: BuildupMode::Always,
+			played_buildups: HashSet::new(),
+			song_start: Duration::new(0, 0),
+
+			music_track: None,
+			endpoint: None,
 		}
 	}
 
-	pub fn extend(&mut self, songs: Vec<Song>) {
-		self.songs.extend(songs);
+	pub fn extend(&mut self, pack: &Path, songs: Vec<Song>) {
+		self.songs.extend(songs.into_iter().map(|mut song| {
+			song.pack = pack.to_owned();
+			song
+		}));
 	}
 
-	pub fn play_song<T: AsRef<str>, S: UiLayout>(&mut self, name: T, ui: &mut S) -> Result<()> {
-		self.get_song_index(name)
-			.map(|index| {
-				self.beat_time = Instant::now();
-				self.beat_index = None;
+	// If the current song goes, it fades out into another one
+	pub fn remove_pack<S: UiLayout>(&mut self, pack: &Path, rng: &mut Random, ui: &mut S) {
+		let current = self.curr_index.map(|index| self.songs[index].pack == pack);
+		let new_index = self.curr_index.map(|index| self.songs[..index].iter().filter(|song| song.pack != pack).count());
+
+		self.songs.retain(|song| song.pack != pack);
 
-				self.music_track = self.songs[index].play(&self.endpoint, ui);
+		match current {
+			Some(false) => self.curr_index = new_index,
+			Some(true) if !self.songs.is_empty() => {
+				// Still pointing at the old one so play_index fades it out
+				let index = rng.gen_range(0, self.songs.len());
+				self.play_index(index, ui);
+			}
+			Some(true) => self.stop(),
+			None => {}
+		}
+	}
+
+	fn stop(&mut self) {
+		self.music_track = self.endpoint.as_ref().map(Sink::new);
+		self.clock = None;
+		self.curr_index = None;
+		self.beat_index = None;
+	}
+
+	pub fn current_pack(&self) -> Option<&Path> {
+		self.curr_index.map(|index| self.songs[index].pack.as_ref())
+	}
+
+	pub fn is_playing(&self) -> bool {
+		self.curr_index.is_some()
+	}
+
+	// In milliseconds, can be negative
+	pub fn set_latency(&mut self, latency: i64) {
+		self.latency = Duration::from_millis(latency.unsigned_abs());
+		self.negative_latency = latency < 0;
+	}
+
+	pub fn set_volume(&mut self, volume: f32) {
+		self.volume = volume.clamp(0.0, 1.0);
+		self.update_volume();
+	}
+
+	pub fn volume(&self) -> f32 {
+		self.volume
+	}
 
-				self.curr_index = Some(index);
-			})
+	pub fn muted(&self) -> bool {
+		self.muted
+	}
+
+	pub fn change_volume(&mut self, change: f32) {
+		let volume = self.volume + change;
+		self.set_volume(volume);
+	}
+
+	pub fn set_muted(&mut self, muted: bool) {
+		self.muted = muted;
+		self.update_volume();
+	}
+
+	pub fn toggle_mute(&mut self) {
+		let muted = !self.muted;
+		self.set_muted(muted);
+	}
+
+	// Zero to cut straight to the next song
+	pub fn set_fade(&mut self, fade_length: Duration) {
+		self.fade_length = fade_length;
+	}
+
+	pub fn set_blackout_on_change(&mut self, blackout: bool) {
+		self.blackout_on_change = blackout;
+	}
+
+	// Only affects songs started after this
+	pub fn set_buildup_mode(&mut self, mode: BuildupMode) {
+		self.buildup_mode = mode;
+	}
+
+	fn current_volume(&self) -> f32 {
+		if self.muted { 0.0 } else { self.volume }
+	}
+
+	fn update_volume(&mut self) {
+		let volume = self.current_volume();
+		if let Some(ref mut sink) = self.music_track {
+			sink.set_volume(volume);
+		}
+	}
+
+	// The beats follow the samples played, so they stop too
+	pub fn toggle_pause(&mut self) {
+		let paused = !self.is_paused();
+		self.set_paused(paused);
+	}
+
+	pub fn is_paused(&self) -> bool {
+		match self.music_track {
+			Some(ref sink) => sink.is_paused(),
+			None => self.clock.as_ref().is_some_and(PlaybackClock::is_paused),
+		}
+	}
+
+	fn set_paused(&mut self, paused: bool) {
+		// Not worth picking back up afterwards
+		if paused {
+			self.fading = None;
+		}
+		if let Some(ref sink) = self.music_track {
+			if paused {
+				sink.pause();
+			} else {
+				sink.play();
+			}
+		}
+		if let Some(ref mut clock) = self.clock {
+			if paused {
+				clock.pause();
+			} else {
+				clock.resume();
+			}
+		}
+	}
+
+	pub fn play_song<T: AsRef<str>, S: UiLayout>(&mut self, name: T, ui: &mut S) -> Result<()> {
+		self.get_song_index(name)
+			.map(|index| self.play_index(index, ui))
 			.ok_or_else(|| "No song.".into())
 	}
 
-	pub fn play_random<S: UiLayout>(&mut self, ui: &mut S) {
+	pub fn play_random<S: UiLayout>(&mut self, rng: &mut Random, ui: &mut S) {
 		if self.songs.is_empty() {
 			return;
 		}
-		let index = rng().gen_range(0, self.songs.len());
-		self.beat_time = Instant::now();
-		self.beat_index = None;
-
-		self.music_track = self.songs[index].play(&self.endpoint, ui);
-
-		self.curr_index = Some(index);
+		let index = rng.gen_range(0, self.songs.len());
+		self.play_index(index, ui);
 	}
 
 	pub fn prev_song<T: UiLayout>(&mut self, ui: &mut T) {
@@ -111,12 +295,7 @@ impl SongManager {
 		let index = self.curr_index
 			.map_or(0, move |index| (index + length - 1) % length);
 
-		self.music_track = self.songs[index].play(&self.endpoint, ui);
-
-		self.beat_time = Instant::now();
-		self.beat_index = None;
-
-		self.curr_index = Some(index);
+		self.play_index(index, ui);
 	}
 
 	pub fn next_song<T: UiLayout>(&mut self, ui: &mut T) {
@@ -127,96 +306,254 @@ impl SongManager {
 		let length = self.songs.len();
 		let index = self.curr_index.map_or(0, move |index| (index + 1) % length);
 
-		self.music_track = self.songs[index].play(&self.endpoint, ui);
+		self.play_index(index, ui);
+	}
+
+	pub fn songs(&self) -> &[Song] {
+		&self.songs
+	}
+
+	pub fn current_song(&self) -> Option<&Song> {
+		self.curr_index.map(|index| &self.songs[index])
+	}
+
+	pub fn play_index<T: UiLayout>(&mut self, index: usize, ui: &mut T) {
+		// Anything still fading from before just gets cut off
+		if self.curr_index.is_some() && self.fade_length > Duration::new(0, 0) {
+			self.fading = self.music_track.take();
+			self.transition = Some(clock::now());
+		}
+		// Skipping the buildup is just starting from the loop, so the beats and clock follow along
+		let start = if self.plays_buildup(index) {
+			Duration::new(0, 0)
+		} else {
+			self.songs[index].buildup_duration
+		};
+		self.played_buildups.insert(self.songs[index].name.clone());
+		self.song_start = start;
+
+		self.start_at(index, start, ui);
+	}
+
+	fn plays_buildup(&self, index: usize) -> bool {
+		match self.buildup_mode {
+			BuildupMode::Always => true,
+			BuildupMode::Once => !self.played_buildups.contains(&self.songs[index].name),
+			BuildupMode::Never => false,
+		}
+	}
+
+	fn start_at<T: UiLayout>(&mut self, index: usize, start: Duration, ui: &mut T) {
+		if let Some(ref endpoint) = self.endpoint {
+			match self.songs[index].play(endpoint, start, ui) {
+				Ok((mut sink, clock)) => {
+					sink.set_volume(self.current_volume());
+					self.music_track = Some(sink);
+					self.clock = Some(clock);
+				}
+				Err(err) => {
+					// Keep the visuals going without it
+					eprintln!("Error playing {}: {}", self.songs[index].title, err);
+					self.music_track = Some(Sink::new(endpoint));
+					self.clock = Some(PlaybackClock::Timer(clock::now(), start));
+				}
+			}
+		} else {
+			ui.update_song(&self.songs[index]);
+			self.clock = Some(PlaybackClock::Timer(clock::now(), start));
+		}
 
-		self.beat_time = Instant::now();
+		// Picked up again next frame, from wherever it is now
 		self.beat_index = None;
 
 		self.curr_index = Some(index);
 	}
 
-	pub fn update_beat<S: UiLayout>(
-		&mut self,
-		screen: &mut Screen,
-		image_manager: &mut ImageManager<WindowContext>,
-		ui: &mut S,
-	) {
+	// Jumps to anywhere in the current song, staying paused if it was
+	pub fn seek<T: UiLayout>(&mut self, time: Duration, ui: &mut T) {
 		if let Some(index) = self.curr_index {
-			let song = &self.songs[index];
-
-			let new_index = song.get_beat_index(self.beat_time.elapsed());
-			if self.beat_index != Some(new_index) {
-				match song.get_beat(new_index) {
-					'.' => {}
-					'-' => {
-						screen.random_colour(ui);
-						image_manager.random_image(ui);
+			let paused = self.is_paused();
+			self.start_at(index, time, ui);
+			if paused {
+				self.set_paused(true);
+			}
+		}
+	}
 
-						screen.clear_blackout();
+	// In whichever loop is playing now, or in the buildup
+	pub fn seek_beat<T: UiLayout>(&mut self, beat_index: BeatIndex, ui: &mut T) {
+		let target = match self.curr_index {
+			Some(index) => {
+				let song = &self.songs[index];
+				match beat_index {
+					BeatIndex::Buildup(beat) => {
+						let beat = beat.min(song.buildup_rhythm.len().saturating_sub(1));
+						song.buildup_beat_length * beat as u32
 					}
-					'o' => {
-						screen.random_colour(ui);
-						image_manager.random_image(ui);
-
-						image_manager.blur_x(ui);
-						screen.clear_blackout();
+					BeatIndex::Loop(beat) => {
+						let loop_start = song.loop_start(song.loop_count(self.position()));
+						loop_start + song.loop_beat_length * (beat % song.rhythm.len()) as u32
 					}
-					'x' => {
-						screen.random_colour(ui);
-						image_manager.random_image(ui);
+				}
+			}
+			None => return,
+		};
+		self.seek(target, ui);
+	}
 
-						image_manager.blur_y(ui);
-						screen.clear_blackout();
-					}
-					'O' => {
-						image_manager.blur_x(ui);
-						screen.clear_blackout();
-					}
-					'X' => {
-						image_manager.blur_y(ui);
-						screen.clear_blackout();
-					}
-					':' => {
-						screen.random_colour(ui);
-						screen.clear_blackout();
-					}
-					'+' => {
-						// blur x?
-						image_manager.blur_x(ui);
-						screen.blackout();
-					}
-					'|' => {
-						screen.short_blackout();
-						screen.random_colour(ui);
+	pub fn next_beat<T: UiLayout>(&mut self, ui: &mut T) {
+		let target = match self.curr_index {
+			Some(index) => {
+				let song = &self.songs[index];
+				let position = self.position();
+				song.beat_start(position) + song.beat_length(song.get_beat_index(position))
+			}
+			None => return,
+		};
+		self.seek(target, ui);
+	}
 
-						// check this
-						image_manager.random_image(ui);
-					}
-					'*' => {
-						image_manager.random_image(ui);
+	pub fn prev_beat<T: UiLayout>(&mut self, ui: &mut T) {
+		let target = match self.curr_index {
+			Some(index) => {
+				let song = &self.songs[index];
+				let start = song.beat_start(self.position());
+				match start.checked_sub(Duration::new(0, 1)) {
+					Some(time) => song.beat_start(time),
+					None => start,
+				}
+			}
+			None => return,
+		};
+		self.seek(target, ui);
+	}
 
-						screen.clear_blackout();
-					}
-					'=' => {
-						image_manager.random_image(ui);
+	// From the buildup to the first loop, or on to the next loop
+	pub fn next_loop<T: UiLayout>(&mut self, ui: &mut T) {
+		let target = match self.curr_index {
+			Some(index) => {
+				let song = &self.songs[index];
+				let position = self.position();
+				if position < song.buildup_duration {
+					song.buildup_duration
+				} else {
+					song.loop_start(song.loop_count(position) + 1)
+				}
+			}
+			None => return,
+		};
+		self.seek(target, ui);
+	}
 
-						let length = song.remaining_beat_time(new_index);
-						screen.fade_random(duration_to_secs(length), ui);
-						screen.clear_blackout();
-					}
-					'~' => {
-						let length = song.remaining_beat_time(new_index);
-						screen.fade_random(duration_to_secs(length), ui);
-						screen.clear_blackout();
-					}
-					ch => println!("TODO: {}", ch),
+	// Back a loop, or back to the start from the first one
+	pub fn prev_loop<T: UiLayout>(&mut self, ui: &mut T) {
+		let target = match self.curr_index {
+			Some(index) => {
+				let song = &self.songs[index];
+				match song.loop_count(self.position()) {
+					0 => self.song_start,
+					count => song.loop_start(count - 1),
+				}
+			}
+			None => return,
+		};
+		self.seek(target, ui);
+	}
+
+	pub fn restart_buildup<T: UiLayout>(&mut self, ui: &mut T) {
+		self.seek_beat(BeatIndex::Buildup(0), ui);
+	}
+
+	// From the editor, the changes show from the next beat on
+	// Does nothing if the song's gone
+	pub fn set_rhythm<S: UiLayout>(&mut self, pack: &Path, name: &str, rhythm: Vec<char>, buildup_rhythm: Vec<char>, ui: &mut S) {
+		let index = match self.songs.iter().position(|song| song.pack() == pack && song.name() == name) {
+			Some(index) => index,
+			None => return,
+		};
+		self.songs[index].set_rhythm(rhythm, buildup_rhythm);
+		if self.curr_index == Some(index) {
+			ui.update_song(&self.songs[index]);
+		}
+	}
+
+	// Whichever beat was last applied
+	pub fn current_beat(&self) -> Option<BeatIndex> {
+		self.beat_index
+	}
+
+	// The beat closest to what's being heard, for tapping along
+	pub fn nearest_beat(&self) -> Option<BeatIndex> {
+		self.current_song().map(|song| {
+			let time = self.playback_time();
+			let half_beat = song.beat_length(song.get_beat_index(time)) / 2;
+			song.get_beat_index(time + half_beat)
+		})
+	}
+
+	// For when the layout changes, the beat and time get updated next frame anyway
+	pub fn update_ui<S: UiLayout>(&self, ui: &mut S) {
+		if let Some(index) = self.curr_index {
+			ui.update_song(&self.songs[index]);
+		}
+	}
+
+	// Buildup, if it's played, plus one time through the loop
+	pub fn song_length(&self) -> Option<Duration> {
+		self.curr_index.map(|index| {
+			self.songs[index].buildup_duration + self.songs[index].loop_duration - self.song_start
+		})
+	}
+
+	// How far into the song the audio has got, what's heard lags behind by the latency
+	// Seeking goes from here, so pressing twice quickly moves twice
+	fn position(&self) -> Duration {
+		self.clock
+			.as_ref()
+			.map_or(Duration::new(0, 0), PlaybackClock::position)
+	}
+
+	// Position in the current song, going by what's been heard
+	fn playback_time(&self) -> Duration {
+		let position = self.position();
+
+		if self.negative_latency {
+			position + self.latency
+		} else if position > self.latency {
+			position - self.latency
+		} else {
+			Duration::new(0, 0)
+		}
+	}
+
+	pub fn update_beat<S: UiLayout, Target>(
+		&mut self,
+		screen: &mut Screen,
+		image_manager: &mut ImageManager<Target>,
+		rng: &mut Random,
+		ui: &mut S,
+	) {
+		let blacked_out = self.update_transition(screen);
+
+		if let Some(index) = self.curr_index {
+			let song = &self.songs[index];
+
+			let time = self.playback_time();
+			let new_index = song.get_beat_index(time);
+			image_manager.update_beat(song.get_beat_position(time));
+			// Whatever beat it's on when the screen comes back gets applied then
+			if self.beat_index != Some(new_index) && !blacked_out {
+				let beat = song.get_beat(new_index);
+				match beats::effect(beat) {
+					Some(effect) => apply_effect(effect, song, new_index, screen, image_manager, rng, ui),
+					None => eprintln!("Unknown beat: {}", beat),
 				}
 				self.beat_index = Some(new_index);
 			}
 
 			// Update ui text
 			{
-				let time = duration_to_secs(self.beat_time.elapsed());
+				let time = duration_to_secs(time);
 				let buildup_time = duration_to_secs(song.buildup_duration);
 				let loop_time = duration_to_secs(song.loop_duration);
 
@@ -228,11 +565,111 @@ impl SongManager {
 		}
 	}
 
+	// Fades out the last song, returns whether the screen should stay black
+	fn update_transition(&mut self, screen: &mut Screen) -> bool {
+		let start = match self.transition {
+			Some(start) => start,
+			None => return false,
+		};
+
+		let progress = duration_to_secs(clock::elapsed(start)) / duration_to_secs(self.fade_length);
+		if progress >= 1.0 {
+			self.fading = None;
+			self.transition = None;
+			if self.blacked_out {
+				screen.clear_blackout();
+				self.blacked_out = false;
+			}
+			return false;
+		}
+
+		let volume = self.current_volume() * (1.0 - progress) as f32;
+		if let Some(ref mut sink) = self.fading {
+			sink.set_volume(volume);
+		}
+
+		if self.blackout_on_change {
+			// Only on the first frame, it fades in by itself
+			if !self.blacked_out {
+				screen.blackout();
+				self.blacked_out = true;
+			}
+			return true;
+		}
+		false
+	}
+
 	fn get_song_index<T: AsRef<str>>(&self, title: T) -> Option<usize> {
 		self.songs
 			.iter()
-			.position(|ref song| song.title == title.as_ref())
+			.position(|song| song.title == title.as_ref())
+	}
+}
+
+fn apply_effect<S: UiLayout, Target>(
+	effect: BeatEffect,
+	song: &Song,
+	beat_index: BeatIndex,
+	screen: &mut Screen,
+	image_manager: &mut ImageManager<Target>,
+	rng: &mut Random,
+	ui: &mut S,
+) {
+	match effect.blackout {
+		Blackout::Keep => {}
+		Blackout::Clear => screen.clear_blackout(),
+		Blackout::Black => screen.blackout(),
+		Blackout::White => screen.whiteout(),
+		Blackout::ShortBlack => screen.short_blackout(),
+		Blackout::ShortWhite => screen.short_whiteout(),
+		Blackout::InstantBlack => screen.instant_blackout(),
+		Blackout::InstantWhite => screen.instant_whiteout(),
+		Blackout::Flash => screen.flash(duration_to_secs(song.beat_length(beat_index))),
+	}
+
+	// Has to know the old colour
+	if let Some(direction) = effect.shutter {
+		screen.shutter(direction, duration_to_secs(song.beat_length(beat_index)));
+	}
+
+	match effect.colour {
+		ColourChange::None => {}
+		ColourChange::Random => screen.random_colour(rng, ui),
+		ColourChange::Fade => {
+			let length = song.remaining_beat_time(beat_index);
+			screen.fade_random(duration_to_secs(length), rng, ui);
+		}
+	}
+
+	if effect.image {
+		image_manager.random_image(rng, ui);
+	}
+
+	match effect.blur {
+		Some(BlurDirection::Horizontal) => image_manager.blur_x(ui),
+		Some(BlurDirection::Vertical) => image_manager.blur_y(ui),
+		None => {}
+	}
+
+	if effect.invert {
+		screen.invert();
+	}
+
+	if let Some(trippy) = effect.trippy {
+		screen.trippy(trippy);
+	}
+
+	if let Some(slice) = effect.slice {
+		image_manager.slice(slice, duration_to_secs(song.beat_length(beat_index)), rng);
+	}
+}
+
+fn whole_beats(time: Duration, length: Duration) -> u32 {
+	let nanos = |d: Duration| d.as_secs() * 1_000_000_000 + d.subsec_nanos() as u64;
+	if nanos(length) == 0 {
+		return 0;
 	}
+	(nanos(time) / nanos(length)) as u32
 }
 
 // TODO: guarantee that this will not be out of bounds for the song
@@ -249,9 +686,14 @@ impl fmt::Debug for Song {
 }
 
 impl Song {
-	pub fn new(song_data: SongData, audio_data: &mut HashMap<String, AudioData>) -> Result<Self> {
+	pub fn new(song_data: SongData, audio_data: &mut HashMap<String, EncodedAudio>) -> Result<Self> {
+		let loop_audio = audio_data
+			.remove(&song_data.name)
+			.ok_or_else(|| format!("Error: Could not find song {}", song_data.name))?;
+
 		let mut song = Song {
 			name: song_data.name,
+			pack: PathBuf::new(),
 			title: song_data.title,
 			source: song_data.source,
 			rhythm: song_data.rhythm,
@@ -264,26 +706,19 @@ impl Song {
 			buildup_beat_length: Duration::new(0, 0),
 
 			// Total length of loop/duration
-			loop_duration: Duration::new(0, 0),
+			loop_duration: loop_audio.duration(),
 			buildup_duration: Duration::new(0, 0),
 
-			loop_audio: empty_audio(),
+			loop_audio,
 			buildup_audio: None,
 		};
 
 		// Calculate beat length/buildup duration + fill in blank buildups
-		if let Some(source) = audio_data.remove(&song.name) {
-			song.loop_duration = source.total_duration().unwrap();
-			song.loop_beat_length = song.loop_duration / song.rhythm.len() as u32;
-
-			song.loop_audio = source;
-		} else {
-			return Err(format!("Error: Could not find song {}", song.name).into());
-		}
+		song.loop_beat_length = song.loop_duration / song.rhythm.len() as u32;
 
 		if let Some(ref buildup) = song.buildup {
 			if let Some(source) = audio_data.remove(buildup) {
-				song.buildup_duration = source.total_duration().unwrap();
+				song.buildup_duration = source.duration();
 				if song.buildup_rhythm.is_empty() {
 					song.buildup_rhythm.push('.');
 				}
@@ -298,6 +733,58 @@ impl Song {
 		Ok(song)
 	}
 
+	pub fn pack(&self) -> &Path {
+		&self.pack
+	}
+
+	// File name of the loop, which is what songs.xml goes by
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn has_buildup(&self) -> bool {
+		self.buildup_audio.is_some()
+	}
+
+	pub fn loop_audio(&self) -> &EncodedAudio {
+		&self.loop_audio
+	}
+
+	// File name and audio
+	pub fn buildup(&self) -> Option<(&str, &EncodedAudio)> {
+		match (self.buildup.as_ref(), self.buildup_audio.as_ref()) {
+			(Some(name), Some(audio)) => Some((name, audio)),
+			_ => None,
+		}
+	}
+
+	// The beats get longer or shorter to fit the audio, so more beats is a finer grid
+	// Neither can be empty
+	pub fn set_rhythm(&mut self, rhythm: Vec<char>, buildup_rhythm: Vec<char>) {
+		self.loop_beat_length = self.loop_duration / rhythm.len() as u32;
+		if self.buildup_audio.is_some() {
+			self.buildup_beat_length = self.buildup_duration / buildup_rhythm.len() as u32;
+		}
+		self.rhythm = rhythm;
+		self.buildup_rhythm = buildup_rhythm;
+	}
+
+	// How loud the loop or the buildup is in each of `slices` even pieces
+	pub fn waveform(&self, buildup: bool, slices: usize) -> Result<Vec<f32>> {
+		let audio = if buildup {
+			self.buildup_audio.as_ref().ok_or("No buildup")?
+		} else {
+			&self.loop_audio
+		};
+		audio.peaks(slices)
+	}
+
+	// How many of the loop's beats would fit in the buildup
+	pub fn buildup_length_in_beats(&self) -> Option<f64> {
+		self.buildup_audio.as_ref()?;
+		Some(duration_to_secs(self.buildup_duration) / duration_to_secs(self.loop_beat_length))
+	}
+
 	fn get_beat_index(&self, beat_time: Duration) -> BeatIndex {
 		if beat_time >= self.buildup_duration {
 			let beat_time = beat_time - self.buildup_duration;
@@ -311,6 +798,24 @@ impl Song {
 		}
 	}
 
+	// Same as get_beat_index, but keeps the fractional part
+	// Not wrapped to the loop length, but it starts again from 0 when the loop starts
+	fn get_beat_position(&self, beat_time: Duration) -> f64 {
+		if beat_time >= self.buildup_duration {
+			let beat_time = beat_time - self.buildup_duration;
+			duration_to_secs(beat_time) / duration_to_secs(self.loop_beat_length)
+		} else {
+			duration_to_secs(beat_time) / duration_to_secs(self.buildup_beat_length)
+		}
+	}
+
+	fn beat_length(&self, beat_index: BeatIndex) -> Duration {
+		match beat_index {
+			BeatIndex::Loop(_) => self.loop_beat_length,
+			BeatIndex::Buildup(_) => self.buildup_beat_length,
+		}
+	}
+
 	fn get_beat(&self, beat_index: BeatIndex) -> char {
 		match beat_index {
 			BeatIndex::Loop(index) => self.rhythm[index % self.rhythm.len()],
@@ -318,17 +823,57 @@ impl Song {
 		}
 	}
 
-	fn play<T: UiLayout>(&self, endpoint: &Endpoint, ui: &mut T) -> Sink {
+	// Starts from anywhere in the song, the clock counts on from there
+	fn play<T: UiLayout>(&self, endpoint: &Endpoint, start: Duration, ui: &mut T) -> Result<(Sink, PlaybackClock)> {
+		ui.update_song(self);
+
 		let sink = Sink::new(endpoint);
-		if let Some(ref buildup) = self.buildup_audio {
-			sink.append(buildup.clone());
+		let mut counters = Vec::with_capacity(2);
+		let loop_audio = self.loop_audio.decode()?;
+		if start < self.buildup_duration {
+			if let Some(ref buildup) = self.buildup_audio {
+				let (source, counter) = Counted::new(audio::skip_duration(buildup.decode()?, start));
+				sink.append(source);
+				counters.push(counter);
+			}
+		} else {
+			// Finish off the loop it starts in before repeating the whole thing
+			let offset = start - self.loop_start(self.loop_count(start));
+			if offset > Duration::new(0, 0) {
+				let (source, counter) = Counted::new(audio::skip_duration(loop_audio.clone(), offset));
+				sink.append(source);
+				counters.push(counter);
+			}
 		}
 
-		sink.append(self.loop_audio.clone().repeat_infinite());
+		let (source, counter) = Counted::new(loop_audio.repeat_infinite());
+		sink.append(source);
+		counters.push(counter);
 
-		ui.update_song(self);
+		Ok((sink, PlaybackClock::Samples(start, counters)))
+	}
 
-		sink
+	// How many times the loop has finished, none during the buildup
+	fn loop_count(&self, time: Duration) -> u32 {
+		if time < self.buildup_duration {
+			return 0;
+		}
+		whole_beats(time - self.buildup_duration, self.loop_duration)
+	}
+
+	fn loop_start(&self, count: u32) -> Duration {
+		self.buildup_duration + self.loop_duration * count
+	}
+
+	// Where the beat playing at time started
+	// Done with whole nanoseconds so seeking to it lands on the same beat again
+	fn beat_start(&self, time: Duration) -> Duration {
+		if time < self.buildup_duration {
+			self.buildup_beat_length * whole_beats(time, self.buildup_beat_length)
+		} else {
+			let beats = whole_beats(time - self.buildup_duration, self.loop_beat_length);
+			self.buildup_duration + self.loop_beat_length * beats
+		}
 	}
 
 	// Fun fact: multiplication isn't commutative for Duration * u32
@@ -336,7 +881,7 @@ impl Song {
 		let buildup_duration = if let BeatIndex::Buildup(idx) = beat_index {
 			let remaining = self.buildup_rhythm.split_at(idx).1;
 			// Find position of first non '.'
-			if let Some(index) = remaining.iter().position(|&beat| beat != '.') {
+			if let Some(index) = remaining.iter().position(|&beat| !beats::is_empty(beat)) {
 				return self.buildup_beat_length * index as u32;
 			} else {
 				self.buildup_beat_length * remaining.len() as u32
@@ -353,18 +898,18 @@ impl Song {
 
 		let (before, remaining) = self.rhythm.split_at(idx);
 		// Find position of first non '.'
-		if let Some(index) = remaining.iter().position(|&beat| beat != '.') {
-			return self.loop_beat_length * index as u32 + buildup_duration;
+		if let Some(index) = remaining.iter().position(|&beat| !beats::is_empty(beat)) {
+			self.loop_beat_length * index as u32 + buildup_duration
 		} else {
 			// The next one is after the loop, if it exists
 			let loop_duration = self.loop_beat_length * remaining.len() as u32 + buildup_duration;
 			
-			if let Some(index) = before.iter().position(|&beat| beat != '.') {
-				return self.loop_beat_length * index as u32 + loop_duration;
+			if let Some(index) = before.iter().position(|&beat| !beats::is_empty(beat)) {
+				self.loop_beat_length * index as u32 + loop_duration
+			} else {
+				self.loop_beat_length * before.len() as u32 + loop_duration
 			}
 
-			return self.loop_beat_length * before.len() as u32 + loop_duration;
-
 			//unreachable!("There must have been a beat right? Unless this is being called for no reason")
 		}
 	}
@@ -1,16 +1,58 @@
 use std::fmt;
 
 use sdl2::pixels::Color as Colour;
-use sdl2::render::{Canvas, RenderTarget, Texture, TextureCreator, TextureQuery};
+use sdl2::render::{Canvas, RenderTarget, TextureCreator};
 use sdl2::rect::Rect;
-use sdl2::video::WindowContext;
 
 use sdl2::ttf::Font;
 
 use Result;
 use songs::{Song, BeatIndex};
+use keymap::Keymap;
+use glyphs::GlyphAtlas;
 
-struct HexNum(i32);
+// The layouts are positioned for this size, then scaled to fit the actual output
+pub const LAYOUT_WIDTH: u32 = 1280;
+pub const LAYOUT_HEIGHT: u32 = 720;
+
+// Scale from layout size to output size, going by the smaller side
+pub fn layout_scale(width: u32, height: u32) -> f64 {
+	(width as f64 / LAYOUT_WIDTH as f64).min(height as f64 / LAYOUT_HEIGHT as f64)
+}
+
+// Scale and top left corner for drawing the layout in the middle of the output
+pub fn centred_layout(width: u32, height: u32) -> (f64, (i32, i32)) {
+	let scale = layout_scale(width, height);
+	let origin = (
+		(width as i32 - (LAYOUT_WIDTH as f64 * scale) as i32) / 2,
+		(height as i32 - (LAYOUT_HEIGHT as f64 * scale) as i32) / 2,
+	);
+	(scale, origin)
+}
+
+// From layout coordinates to the output
+pub fn scale_rect(rect: Rect, scale: f64, origin: (i32, i32)) -> Rect {
+	Rect::new(
+		origin.0 + (rect.x() as f64 * scale) as i32,
+		origin.1 + (rect.y() as f64 * scale) as i32,
+		(rect.width() as f64 * scale) as u32,
+		(rect.height() as f64 * scale) as u32,
+	)
+}
+
+// The next count beats from beat, and the beat number to display
+// Buildup beats count up to zero so the loop always starts at 0
+pub fn upcoming_beats(rhythm: &[char], buildup_rhythm: &[char], beat: BeatIndex, count: usize) -> (String, i32) {
+	match beat {
+		BeatIndex::Loop(idx) => (rhythm.iter().cycle().skip(idx).take(count).collect(), idx as i32),
+		BeatIndex::Buildup(idx) => (
+			buildup_rhythm.iter().skip(idx).chain(rhythm.iter().cycle()).take(count).collect(),
+			idx as i32 - buildup_rhythm.len() as i32,
+		),
+	}
+}
+
+pub struct HexNum(pub i32);
 
 impl fmt::Display for HexNum {
 	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -25,34 +67,104 @@ impl fmt::Display for HexNum {
 }
 
 pub struct TextUi {
-	texture: Texture,
+	text: String,
+	colour: Colour,
+	// Size is what it'd be unscaled
 	rect: Rect,
 }
 
 impl TextUi {
-	pub fn create<T: AsRef<str>, Target>(
-		text: T,
-		font: &Font,
-		texture_creator: &TextureCreator<Target>,
-	) -> Result<Self> {
-		let surface = font.render(text.as_ref())
-			.blended(Colour::RGBA(0, 0, 0, 255))?;
-		let texture = texture_creator.create_texture_from_surface(&surface)?;
-		let TextureQuery { width, height, .. } = texture.query();
-		let rect = Rect::new(0, 0, width, height);
+	pub fn create<T: AsRef<str>, Target>(text: T, atlas: &mut GlyphAtlas<Target>) -> Result<Self> {
+		let mut text_ui = TextUi {
+			text: String::new(),
+			colour: Colour::RGBA(0, 0, 0, 255),
+			rect: Rect::new(0, 0, 1, 1),
+		};
+		text_ui.set_text(text, atlas)?;
+		Ok(text_ui)
+	}
 
-		Ok(TextUi { texture, rect })
+	pub fn draw<T: RenderTarget, Target>(&self, canvas: &mut Canvas<T>, atlas: &mut GlyphAtlas<Target>) -> Result<()> {
+		self.draw_scaled(canvas, atlas, 1.0, (0, 0))
 	}
 
-	pub fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<()> {
-		canvas.copy(&self.texture, None, Some(self.rect))?;
+	// Scaled up from the layout size, relative to origin
+	pub fn draw_scaled<T: RenderTarget, Target>(
+		&self,
+		canvas: &mut Canvas<T>,
+		atlas: &mut GlyphAtlas<Target>,
+		scale: f64,
+		origin: (i32, i32),
+	) -> Result<()> {
+		let (output_width, _) = canvas.output_size()?;
+		atlas.set_colour(self.colour);
+
+		let top = origin.1 + (self.rect.y() as f64 * scale) as i32;
+		let mut x = self.rect.x() as f64;
+		for ch in self.text.chars() {
+			let glyph = match atlas.glyph(ch) {
+				Some(glyph) => glyph,
+				None => continue,
+			};
+			// Work out both edges so rounding doesn't leave gaps
+			let left = origin.0 + (x * scale) as i32;
+			let right = origin.0 + ((x + glyph.width() as f64) * scale) as i32;
+			x += glyph.width() as f64;
+			if left >= output_width as i32 {
+				break;
+			}
+
+			let height = ((glyph.height() as f64 * scale) as u32).max(1);
+			let rect = Rect::new(left, top, ((right - left) as u32).max(1), height);
+			canvas.copy(atlas.texture(), Some(glyph), Some(rect))?;
+		}
+		Ok(())
+	}
+
+	// Reads from the bottom up, with the rect's position as the bottom left corner
+	pub fn draw_vertical_scaled<T: RenderTarget, Target>(
+		&self,
+		canvas: &mut Canvas<T>,
+		atlas: &mut GlyphAtlas<Target>,
+		scale: f64,
+		origin: (i32, i32),
+	) -> Result<()> {
+		atlas.set_colour(self.colour);
+
+		let centre_x = origin.0 as f64 + (self.rect.x() as f64 + self.rect.height() as f64 / 2.0) * scale;
+		let mut y = self.rect.y() as f64;
+		for ch in self.text.chars() {
+			let glyph = match atlas.glyph(ch) {
+				Some(glyph) => glyph,
+				None => continue,
+			};
+			let (width, height) = (glyph.width() as f64 * scale, glyph.height() as f64 * scale);
+			let centre_y = origin.1 as f64 + (y - glyph.width() as f64 / 2.0) * scale;
+			y -= glyph.width() as f64;
+			if centre_y < -width {
+				break;
+			}
+
+			// Rotating about the centre keeps the centre where it is
+			let rect = Rect::new(
+				(centre_x - width / 2.0) as i32,
+				(centre_y - height / 2.0) as i32,
+				(width as u32).max(1),
+				(height as u32).max(1),
+			);
+			canvas.copy_ex(atlas.texture(), Some(glyph), Some(rect), -90.0, None, false, false)?;
+		}
 		Ok(())
 	}
 
+	pub fn width(&self) -> u32 {
+		self.rect.width()
+	}
+
 	pub fn centre(&mut self, x: i32, y: i32, w: u32, h: u32) {
 		//center_on(rect.center())
-		let x = x + (w - self.rect.width()) as i32 / 2;
-		let y = y + (h - self.rect.height()) as i32 / 2;
+		let x = x + (w as i32 - self.rect.width() as i32) / 2;
+		let y = y + (h as i32 - self.rect.height() as i32) / 2;
 
 		self.rect.reposition((x, y));
 	}
@@ -61,21 +173,20 @@ impl TextUi {
 		self.rect.reposition((x, y));
 	}
 
-	pub fn set_text<T: AsRef<str>, Target>(
-		&mut self,
-		text: T,
-		font: &Font,
-		texture_creator: &TextureCreator<Target>,
-	) -> Result<()> {
-		let surface = font.render(text.as_ref())
-			.blended(Colour::RGBA(0, 0, 0, 255))?;
-		let texture = texture_creator.create_texture_from_surface(&surface)?;
-		let TextureQuery { width, height, .. } = texture.query();
-		let x = self.rect.left();
-		let y = self.rect.top();
+	pub fn set_colour(&mut self, colour: Colour) {
+		self.colour = colour;
+	}
+
+	pub fn set_text<T: AsRef<str>, Target>(&mut self, text: T, atlas: &mut GlyphAtlas<Target>) -> Result<()> {
+		let text = text.as_ref();
+		if text == self.text {
+			return Ok(());
+		}
 
-		self.texture = texture;
-		self.rect = Rect::new(x, y, width, height);
+		atlas.add_glyphs(text)?;
+		let width = atlas.measure(text).max(1);
+		self.rect.resize(width, atlas.line_height());
+		self.text = text.to_string();
 
 		Ok(())
 	}
@@ -91,15 +202,14 @@ pub trait UiLayout {
 	fn update_y_blur(&mut self, y: f64);
 	fn update_song(&mut self, song: &Song);
 
-	fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<()>;
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()>;
 }
 
 
 //
 
-pub struct BasicUi<'a> {
-	font: &'a Font<'a, 'static>,
-	texture_creator: &'a TextureCreator<WindowContext>,
+pub struct BasicUi<'a, Target: 'a> {
+	atlas: GlyphAtlas<'a, Target>,
 
 	mode_text: TextUi,
 
@@ -123,52 +233,52 @@ pub struct BasicUi<'a> {
 	//
 	buildup_rhythm: Vec<char>,
 	rhythm: Vec<char>,
+	beat: Option<BeatIndex>,
 }
 
-// TODO: draw characters instead of rendering the text
-impl<'a> BasicUi<'a> {
+impl<'a, Target> BasicUi<'a, Target> {
 	pub fn new(
 		font: &'a Font<'a, 'static>,
-		texture_creator: &'a TextureCreator<WindowContext>,
+		texture_creator: &'a TextureCreator<Target>,
 	) -> Self {
-		let mut mode_text = TextUi::create("M=FULL AUTO", &font, &texture_creator).unwrap();
+		let mut atlas = GlyphAtlas::new(font, texture_creator).unwrap();
+
+		let mut mode_text = TextUi::create("M=FULL AUTO", &mut atlas).unwrap();
 		mode_text.set_pos(0, 588);
 
-		let mut image_text = TextUi::create("I=", &font, &texture_creator).unwrap();
+		let mut image_text = TextUi::create("I=", &mut atlas).unwrap();
 		image_text.set_pos(0, 600);
-		let mut timer_text = TextUi::create("T=$0x00000", &font, &texture_creator).unwrap();
+		let mut timer_text = TextUi::create("T=$0x00000", &mut atlas).unwrap();
 		timer_text.set_pos(0, 612);
-		let mut beat_text = TextUi::create("B=$0x0000", &font, &texture_creator).unwrap();
+		let mut beat_text = TextUi::create("B=$0x0000", &mut atlas).unwrap();
 		beat_text.set_pos(0, 624);
 
-		let mut x_blur_text = TextUi::create("X=$0x00", &font, &texture_creator).unwrap();
+		let mut x_blur_text = TextUi::create("X=$0x00", &mut atlas).unwrap();
 		x_blur_text.set_pos(0, 636);
-		let mut y_blur_text = TextUi::create("Y=$0x00", &font, &texture_creator).unwrap();
+		let mut y_blur_text = TextUi::create("Y=$0x00", &mut atlas).unwrap();
 		y_blur_text.set_pos(0, 648);
 
-		let mut colour_index_text = TextUi::create("C=$0x00", &font, &texture_creator).unwrap();
+		let mut colour_index_text = TextUi::create("C=$0x00", &mut atlas).unwrap();
 		colour_index_text.set_pos(0, 660);
-		let mut colour_name_text = TextUi::create("BLACK", &font, &texture_creator).unwrap();
+		let mut colour_name_text = TextUi::create("BLACK", &mut atlas).unwrap();
 		colour_name_text.set_pos(0, 684);
 
-		let mut version_text = TextUi::create("V=$1", &font, &texture_creator).unwrap();
+		let mut version_text = TextUi::create("V=$1", &mut atlas).unwrap();
 		version_text.set_pos(0, 672);
 
-		// Got to be careful, sdl_ttf doesn't like empty strings
-		let mut song_text = TextUi::create(" ", &font, &texture_creator).unwrap();
+		let mut song_text = TextUi::create(" ", &mut atlas).unwrap();
 		song_text.set_pos(0, 696);
 
 
-		let mut rhythm_marker = TextUi::create(">>", &font, &texture_creator).unwrap();
+		let mut rhythm_marker = TextUi::create(">>", &mut atlas).unwrap();
 		rhythm_marker.set_pos(0, 708);
-		let width = rhythm_marker.rect.width() as i32;
+		let width = rhythm_marker.width() as i32;
 		
-		let mut rhythm_text = TextUi::create(" ", &font, &texture_creator).unwrap();
+		let mut rhythm_text = TextUi::create(" ", &mut atlas).unwrap();
 		rhythm_text.set_pos(width, 708);
 
 		BasicUi {
-			font,
-			texture_creator,
+			atlas,
 
 			mode_text,
 
@@ -191,125 +301,163 @@ impl<'a> BasicUi<'a> {
 
 			buildup_rhythm: Vec::new(),
 			rhythm: Vec::new(),
+			beat: None,
 		}
 	}
 }
 
-impl<'a> UiLayout for BasicUi<'a> {
+impl<'a, Target> UiLayout for BasicUi<'a, Target> {
 	fn update_mode(&mut self, full_auto: bool) {
 		let text = if full_auto { "FULL AUTO" } else { "NORMAL" };
 		self.mode_text
-			.set_text(format!("M={}", text), self.font, self.texture_creator)
+			.set_text(format!("M={}", text), &mut self.atlas)
 			.unwrap();
 	}
 	fn update_time(&mut self, time: i32) {
 		self.timer_text
-			.set_text(
-				format!("T={:5}", HexNum(time)),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("T={:5}", HexNum(time)), &mut self.atlas)
 			.unwrap();
 	}
 
-	// TODO: better way to do this
-	// Possibilities:
-	// 1. render copy characters manually each beat
-	// 2. prerender the entire thing and only display part of it
 	fn update_beat(&mut self, beat: BeatIndex) {
-		let index = match beat {
-			BeatIndex::Loop(idx) => {
-				let beat_string: String = self.rhythm.iter().cycle().skip(idx).take(256).collect();
-				self.rhythm_text.set_text(beat_string, self.font, self.texture_creator).unwrap();
-				idx as i32
-			},
-			BeatIndex::Buildup(idx) => {
-				let beat_string: String = self.buildup_rhythm.iter().skip(idx).chain(self.rhythm.iter().cycle()).take(256).collect();
-				self.rhythm_text.set_text(beat_string, self.font, self.texture_creator).unwrap();
-				idx as i32 - self.buildup_rhythm.len() as i32
-			},
-		};
+		// Gets called every frame, but only changes on the beat
+		if self.beat == Some(beat) {
+			return;
+		}
+		self.beat = Some(beat);
+
+		let (beat_string, index) = upcoming_beats(&self.rhythm, &self.buildup_rhythm, beat, 256);
+		self.rhythm_text.set_text(beat_string, &mut self.atlas).unwrap();
 		self.beat_text
-			.set_text(
-				format!("B={:4}", HexNum(index)),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("B={:4}", HexNum(index)), &mut self.atlas)
 			.unwrap();
 	}
 
 	fn update_image(&mut self, image_name: &str) {
 		self.image_text
-			.set_text(
-				format!("I={}", image_name).to_uppercase(),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("I={}", image_name).to_uppercase(), &mut self.atlas)
 			.unwrap();
 	}
 
 	fn update_colour(&mut self, index: usize, name: &str) {
 		self.colour_index_text
-			.set_text(
-				format!("C={:2}", HexNum(index as i32)),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("C={:2}", HexNum(index as i32)), &mut self.atlas)
 			.unwrap();
 		self.colour_name_text
-			.set_text(name.to_uppercase(), self.font, self.texture_creator)
+			.set_text(name.to_uppercase(), &mut self.atlas)
 			.unwrap();
 	}
 
 	fn update_x_blur(&mut self, x: f64) {
 		let x = if x >= 1.0 { 255 } else { (x * 256.0) as i32 };
 		self.x_blur_text
-			.set_text(
-				format!("X={:2}", HexNum(x)),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("X={:2}", HexNum(x)), &mut self.atlas)
 			.unwrap();
 	}
 
 	fn update_y_blur(&mut self, y: f64) {
 		let y = if y >= 1.0 { 255 } else { (y * 256.0) as i32 };
 		self.y_blur_text
-			.set_text(
-				format!("Y={:2}", HexNum(y)),
-				self.font,
-				self.texture_creator,
-			)
+			.set_text(format!("Y={:2}", HexNum(y)), &mut self.atlas)
 			.unwrap();
 	}
 
 	fn update_song(&mut self, song: &Song) {
 		self.song_text
-			.set_text(song.title.to_uppercase(), self.font, self.texture_creator)
+			.set_text(song.title.to_uppercase(), &mut self.atlas)
 			.unwrap();
 
 		self.buildup_rhythm = song.buildup_rhythm.clone();
 		self.rhythm = song.rhythm.clone();
+		self.beat = None;
 	}
 
-	fn draw<T: RenderTarget>(&self, canvas: &mut Canvas<T>) -> Result<()> {
-		self.mode_text.draw(canvas)?;
+	fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		// Keep it in the bottom left corner, scaled with the smaller side
+		let (width, height) = canvas.output_size()?;
+		let scale = layout_scale(width, height);
+		let origin = (0, height as i32 - (LAYOUT_HEIGHT as f64 * scale) as i32);
+
+		self.mode_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		self.image_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.timer_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.beat_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+
+		self.x_blur_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.y_blur_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
 
-		self.image_text.draw(canvas)?;
-		self.timer_text.draw(canvas)?;
-		self.beat_text.draw(canvas)?;
+		self.colour_index_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.version_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.colour_name_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
 
-		self.x_blur_text.draw(canvas)?;
-		self.y_blur_text.draw(canvas)?;
+		self.song_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
 
-		self.colour_index_text.draw(canvas)?;
-		self.version_text.draw(canvas)?;
-		self.colour_name_text.draw(canvas)?;
+		self.rhythm_marker.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		self.rhythm_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
 
-		self.song_text.draw(canvas)?;
+		Ok(())
+	}
+}
+
+// List of keys, built from the keymap so it's always up to date
+pub struct HelpOverlay<'a, Target: 'a> {
+	atlas: GlyphAtlas<'a, Target>,
+	lines: Vec<(TextUi, TextUi)>,
+	// In layout coordinates
+	rect: Rect,
+}
 
-		self.rhythm_marker.draw(canvas)?;
-		self.rhythm_text.draw(canvas)?;
+impl<'a, Target> HelpOverlay<'a, Target> {
+	pub fn new(
+		keymap: &Keymap,
+		font: &'a Font<'a, 'static>,
+		texture_creator: &'a TextureCreator<Target>,
+	) -> Result<Self> {
+		let mut atlas = GlyphAtlas::new(font, texture_creator)?;
+		let help_lines = keymap.help_lines();
+		let line_height = 14;
+		let key_width = 120;
+		let padding = 12;
+
+		let mut lines = Vec::with_capacity(help_lines.len());
+		let mut width = 0;
+		for (key, description) in help_lines {
+			let key_text = TextUi::create(key.to_uppercase(), &mut atlas)?;
+			let description_text = TextUi::create(description.to_uppercase(), &mut atlas)?;
+			width = width.max(key_width + description_text.width());
+			lines.push((key_text, description_text));
+		}
+
+		let height = lines.len() as u32 * line_height;
+		let rect = Rect::new(
+			(LAYOUT_WIDTH - width) as i32 / 2 - padding,
+			(LAYOUT_HEIGHT - height) as i32 / 2 - padding,
+			width + padding as u32 * 2,
+			height + padding as u32 * 2,
+		);
+
+		for (i, &mut (ref mut key_text, ref mut description_text)) in lines.iter_mut().enumerate() {
+			let y = rect.y() + padding + (i as u32 * line_height) as i32;
+			key_text.set_pos(rect.x() + padding, y);
+			description_text.set_pos(rect.x() + padding + key_width as i32, y);
+		}
+
+		Ok(HelpOverlay { atlas, lines, rect })
+	}
+
+	pub fn draw<T: RenderTarget>(&mut self, canvas: &mut Canvas<T>) -> Result<()> {
+		// Centred, scaled with the smaller side
+		let (width, height) = canvas.output_size()?;
+		let (scale, origin) = centred_layout(width, height);
+
+		canvas.set_draw_color(Colour::RGBA(0xFF, 0xFF, 0xFF, 0xC0));
+		canvas.fill_rect(scale_rect(self.rect, scale, origin))?;
+
+		for (key_text, description_text) in &self.lines {
+			key_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+			description_text.draw_scaled(canvas, &mut self.atlas, scale, origin)?;
+		}
 
 		Ok(())
 	}
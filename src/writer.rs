@@ -0,0 +1,137 @@
+// Writes a loaded pack back out as a zip or a folder, laid out the way 0x40 packs usually are:
+// info.xml, songs.xml and images.xml at the top, with the files in Songs/ and Images/
+// Loading what comes out gives the same pack back
+
+extern crate xml;
+
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use writer::xml::writer::{EmitterConfig, EventWriter, XmlEvent};
+
+use archive::PackWriter;
+use images::Align;
+use loader::{ImageLoader, PackInfo, ResPack};
+use songs::Song;
+use Result;
+
+// Won't write over anything that's already there
+pub fn write_respack<T: AsRef<Path>>(pack: &ResPack, path: T) -> Result<()> {
+	let path = path.as_ref();
+	if path.exists() {
+		return Err(format!("{} already exists", path.display()).into());
+	}
+
+	let mut writer = PackWriter::create(path)?;
+	writer.add_file("info.xml", &info_xml(&pack.info)?)?;
+	writer.add_file("songs.xml", &songs_xml(&pack.songs)?)?;
+	writer.add_file("images.xml", &images_xml(&pack.images)?)?;
+
+	for song in &pack.songs {
+		let audio = song.loop_audio();
+		writer.add_file(&format!("Songs/{}.{}", song.name(), audio.format().extension()), audio.data())?;
+		if let Some((name, audio)) = song.buildup() {
+			writer.add_file(&format!("Songs/{}.{}", name, audio.format().extension()), audio.data())?;
+		}
+	}
+	// Animations keep their numbered frames, so they get put back together the same way
+	for image in &pack.images {
+		for (name, data) in &image.files {
+			writer.add_file(&format!("Images/{}", name), data)?;
+		}
+	}
+
+	writer.finish()
+}
+
+fn info_xml(info: &PackInfo) -> Result<Vec<u8>> {
+	let mut data = Vec::new();
+	{
+		let mut writer = xml_writer(&mut data);
+		writer.write(XmlEvent::start_element("info"))?;
+		write_field(&mut writer, "name", Some(info.name()))?;
+		write_field(&mut writer, "author", info.author())?;
+		write_field(&mut writer, "description", info.description())?;
+		write_field(&mut writer, "link", info.link())?;
+		writer.write(XmlEvent::end_element())?;
+	}
+	Ok(data)
+}
+
+fn songs_xml(songs: &[Song]) -> Result<Vec<u8>> {
+	let mut data = Vec::new();
+	{
+		let mut writer = xml_writer(&mut data);
+		writer.write(XmlEvent::start_element("songs"))?;
+		for song in songs {
+			let rhythm: String = song.rhythm.iter().collect();
+			let buildup_rhythm: String = song.buildup_rhythm.iter().collect();
+
+			writer.write(XmlEvent::start_element("song").attr("name", song.name()))?;
+			write_field(&mut writer, "title", Some(&song.title))?;
+			write_field(&mut writer, "source", song.source.as_ref().map(String::as_ref))?;
+			write_field(&mut writer, "rhythm", Some(&rhythm))?;
+			write_field(&mut writer, "buildup", song.buildup().map(|(name, _)| name))?;
+			if !buildup_rhythm.is_empty() {
+				write_field(&mut writer, "buildupRhythm", Some(&buildup_rhythm))?;
+			}
+			writer.write(XmlEvent::end_element())?;
+		}
+		writer.write(XmlEvent::end_element())?;
+	}
+	Ok(data)
+}
+
+fn images_xml(images: &[ImageLoader]) -> Result<Vec<u8>> {
+	let mut data = Vec::new();
+	{
+		let mut writer = xml_writer(&mut data);
+		writer.write(XmlEvent::start_element("images"))?;
+		for image in images {
+			let frame_durations: Vec<String> = image
+				.frame_durations
+				.iter()
+				.map(|&duration| duration_to_millis(duration).to_string())
+				.collect();
+			let beats_per_anim = image.beats_per_anim.map(|beats| beats.to_string());
+
+			writer.write(XmlEvent::start_element("image").attr("name", &image.name))?;
+			write_field(&mut writer, "fullname", image.fullname.as_ref().map(String::as_ref))?;
+			write_field(&mut writer, "source", image.source.as_ref().map(String::as_ref))?;
+			write_field(&mut writer, "source_other", image.source_other.as_ref().map(String::as_ref))?;
+			if image.align != Align::default() {
+				write_field(&mut writer, "align", Some(image.align.name()))?;
+			}
+			if !frame_durations.is_empty() {
+				write_field(&mut writer, "frameDuration", Some(&frame_durations.join(",")))?;
+			}
+			write_field(&mut writer, "beatsPerAnim", beats_per_anim.as_ref().map(String::as_ref))?;
+			writer.write(XmlEvent::end_element())?;
+		}
+		writer.write(XmlEvent::end_element())?;
+	}
+	Ok(data)
+}
+
+fn xml_writer<W: Write>(sink: W) -> EventWriter<W> {
+	EmitterConfig::new()
+		.perform_indent(true)
+		.indent_string("\t")
+		.create_writer(sink)
+}
+
+// <name>text</name>, left out if there's nothing to put in it
+fn write_field<W: Write>(writer: &mut EventWriter<W>, name: &str, text: Option<&str>) -> Result<()> {
+	if let Some(text) = text {
+		writer.write(XmlEvent::start_element(name))?;
+		writer.write(XmlEvent::characters(text))?;
+		writer.write(XmlEvent::end_element())?;
+	}
+	Ok(())
+}
+
+// Frame durations are whole milliseconds, whether they came from the xml or a gif
+fn duration_to_millis(duration: Duration) -> u64 {
+	duration.as_secs() * 1000 + u64::from(duration.subsec_millis())
+}